@@ -0,0 +1,173 @@
+//! Validating the config file without starting the display, see `sil check-config`.
+
+use {
+    std::{
+        fmt,
+        time::Duration,
+    },
+    futures::{
+        sink::SinkExt as _,
+        stream::StreamExt as _,
+    },
+    gefolge_web_lib::websocket::{
+        ClientMessageV2,
+        ServerMessageV2,
+    },
+    tokio::time::timeout,
+    url::Url,
+    wheel::fs,
+    crate::{
+        Error,
+        config::Config,
+        font,
+        image::Image,
+        install,
+    },
+};
+
+/// The keys of the `modes` object in the config file.
+const MODES: [&str; 4] = ["binaryTime", "closeWindows", "hexagesimalTime", "newYear"];
+
+#[derive(clap::Args)]
+pub(crate) struct Args {
+    /// Also connect to the server and check that the API key is accepted
+    #[clap(long)]
+    test_credentials: bool,
+}
+
+#[derive(Default)]
+struct Report {
+    errors: usize,
+    warnings: usize,
+}
+
+impl Report {
+    fn error(&mut self, msg: impl fmt::Display) {
+        println!("error: {msg}");
+        self.errors += 1;
+    }
+
+    fn warning(&mut self, msg: impl fmt::Display) {
+        println!("warning: {msg}");
+        self.warnings += 1;
+    }
+}
+
+/// Locates and validates the config file. Returns the exit code.
+pub(crate) async fn check_config(Args { test_credentials }: Args) -> Result<i32, Error> {
+    let mut report = Report::default();
+    let config = if let Some(path) = Config::path().await? {
+        println!("checking {}", path.display());
+        let buf = fs::read(&path).await?;
+        let mut unknown_fields = Vec::default();
+        let mut deserializer = serde_json::Deserializer::from_slice(&buf);
+        let config = match serde_ignored::deserialize(&mut deserializer, |field| unknown_fields.push(field.to_string())).and_then(|config: Config| deserializer.end().map(|()| config)) {
+            Ok(config) => config,
+            Err(e) => {
+                // serde_json errors include the line and column
+                report.error(format_args!("{}: {e}", path.display()));
+                return Ok(1)
+            }
+        };
+        for field in unknown_fields {
+            match field.strip_prefix("modes.") {
+                Some(mode) if !mode.contains('.') => report.error(format_args!("unknown mode {mode:?}, expected one of {}", MODES.join(", "))),
+                _ => report.error(format_args!("unknown field {field}")),
+            }
+        }
+        config
+    } else {
+        println!("no config file found, checking the default config");
+        Config::default()
+    };
+    match Url::parse(&config.ws_url) {
+        Ok(url) if matches!(url.scheme(), "ws" | "wss") => {}
+        Ok(url) => report.error(format_args!("wsUrl: unsupported scheme {:?}, expected \"ws\" or \"wss\"", url.scheme())),
+        Err(e) => report.error(format_args!("wsUrl: {e}")),
+    }
+    match Url::parse(&config.logo_url) {
+        Ok(url) if url.scheme() == "https" => {}
+        Ok(url) => report.error(format_args!("logoUrl: unsupported scheme {:?}, expected \"https\"", url.scheme())),
+        Err(e) => report.error(format_args!("logoUrl: {e}")),
+    }
+    for (name, font_config) in [("regular", &config.fonts.regular), ("bold", &config.fonts.bold), ("display", &config.fonts.display)] {
+        if font_config.path.is_some() {
+            if let Err(e) = font::load(font_config).await {
+                report.error(format_args!("fonts.{name}: {e}"));
+            }
+        } else {
+            match font::find(&font_config.family, font_config.weight).await {
+                Ok(Some(_)) => {}
+                Ok(None) => report.warning(format_args!("fonts.{name}: {:?} ({:?}) is not installed, the bundled DejaVu Sans will be used instead", font_config.family, font_config.weight)),
+                Err(e) => report.error(format_args!("fonts.{name}: {e}")),
+            }
+        }
+    }
+    for (name, path) in &config.icons {
+        match fs::read(path).await {
+            Ok(data) => if let Err(e) = Image::decode(&data) {
+                report.error(format_args!("icons.{name}: failed to decode {}: {e}", path.display()));
+            },
+            Err(e) => report.error(format_args!("icons.{name}: {e}")),
+        }
+    }
+    match (config.update.url(), config.update.public_key()) {
+        (Some(url), public_key) => {
+            match Url::parse(&url.replace("{version}", env!("CARGO_PKG_VERSION"))) {
+                Ok(parsed) if parsed.scheme() == "https" => if !url.contains("{version}") {
+                    report.warning("update.url: doesn't contain {version}, so the same file will be downloaded for every version");
+                },
+                Ok(parsed) => report.error(format_args!("update.url: unsupported scheme {:?}, expected \"https\"", parsed.scheme())),
+                Err(e) => report.error(format_args!("update.url: {e}")),
+            }
+            match public_key {
+                Some(public_key) => if let Err(e) = install::parse_public_key(public_key) {
+                    report.error(format_args!("update.publicKey: {e}"));
+                },
+                None => report.error("update.publicKey: missing, required to verify updates downloaded from update.url"),
+            }
+        }
+        (None, _) => if config.update.public_key.is_some() {
+            report.warning("update.publicKey: has no effect without update.url");
+        },
+    }
+    if !(0.1..=1.0).contains(&config.display.render_scale) {
+        report.warning(format_args!("display.renderScale: {} will be clamped to the range from 0.1 to 1", config.display.render_scale));
+    }
+    if config.scheduler.rotation_interval_secs == 0 {
+        report.warning("scheduler.rotationIntervalSecs: 0 will be treated as 1");
+    }
+    if config.modes.close_windows.duration_mins == 0 {
+        report.warning("modes.closeWindows.durationMins: 0 means the mode is never shown");
+    }
+    if config.api_key().is_err() {
+        if test_credentials {
+            report.error("apiKey: missing");
+        } else {
+            report.warning("apiKey: missing, sil can only run with --mock-event");
+        }
+    } else if test_credentials {
+        println!("connecting to {}", config.ws_url);
+        match timeout(Duration::from_secs(30), check_credentials(&config)).await {
+            Ok(Ok(msg)) => println!("{msg}"),
+            Ok(Err(e)) => report.error(format_args!("failed to authenticate: {e}")),
+            Err(_) => report.error("failed to authenticate: timed out waiting for a response from the server"),
+        }
+    }
+    println!("{} error(s), {} warning(s)", report.errors, report.warnings);
+    Ok(if report.errors > 0 { 1 } else { 0 })
+}
+
+async fn check_credentials(config: &Config) -> Result<String, Error> {
+    let (mut sink, mut stream) = async_proto::websocket027(String::from(config.display.ws_url(&config.ws_url)?)).await?;
+    sink.send(ClientMessageV2::Auth { api_key: config.api_key()?.to_owned() }).await?;
+    sink.send(ClientMessageV2::CurrentEvent).await?;
+    loop {
+        match stream.next().await.ok_or(Error::EndOfStream)?? {
+            ServerMessageV2::Ping | ServerMessageV2::LatestSilVersion(_) => {}
+            ServerMessageV2::Error { debug, display } => return Err(Error::Server { debug, display }),
+            ServerMessageV2::NoEvent => return Ok("credentials accepted, no current event".to_owned()),
+            ServerMessageV2::CurrentEvent { id: _, timezone } => return Ok(format!("credentials accepted, current event in {timezone}")),
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! The source of the current time for the scheduler and renderer, so they can be run at a time other than now.
+
+use {
+    std::{
+        str::FromStr,
+        time::{
+            Duration,
+            Instant,
+        },
+    },
+    chrono::{
+        TimeDelta,
+        prelude::*,
+    },
+};
+
+/// The fastest supported `--time-scale`, which rehearses a day in under a second. Anything faster would quickly exceed the range of dates.
+const MAX_SCALE: f64 = 100_000.0;
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid time scale {0:?}, expected a number between 0 and 100000")]
+pub(crate) struct ScaleParseError(String);
+
+/// Parses `--time-scale`.
+pub(crate) fn parse_scale(s: &str) -> Result<f64, ScaleParseError> {
+    s.parse::<f64>().ok().filter(|scale| (0.0..=MAX_SCALE).contains(scale)).ok_or_else(|| ScaleParseError(s.to_owned()))
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid time {0:?}, expected e.g. 2024-12-31T23:55:00 (in the event's timezone) or 2024-12-31T23:55:00+01:00")]
+pub(crate) struct FakeTimeParseError(String);
+
+/// The time to start at, see `--fake-time`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum FakeTime {
+    Absolute(DateTime<Utc>),
+    /// A wall-clock time in the event's timezone, which is only known once the server has told us about the current event.
+    Local(NaiveDateTime),
+}
+
+impl FromStr for FakeTime {
+    type Err = FakeTimeParseError;
+
+    fn from_str(s: &str) -> Result<Self, FakeTimeParseError> {
+        if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Self::Absolute(time.with_timezone(&Utc)))
+        }
+        for format in ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+            if let Ok(time) = NaiveDateTime::parse_from_str(s, format) {
+                return Ok(Self::Local(time))
+            }
+        }
+        Err(FakeTimeParseError(s.to_owned()))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Clock {
+    /// The real time.
+    System,
+    /// Shows `start` at the real instant `anchor` and advances `scale` times as fast as real time from there.
+    Simulated {
+        start: DateTime<Utc>,
+        anchor: Instant,
+        scale: f64,
+    },
+    /// Will become [`Clock::Simulated`] once the timezone is known, see [`Clock::resolve`]. Shows the real time until then.
+    Pending {
+        start: NaiveDateTime,
+        scale: f64,
+    },
+    /// Always shows the same time, e.g. for rendering a state to a file.
+    Fixed(DateTime<Utc>),
+}
+
+impl Clock {
+    pub(crate) fn new(fake_time: Option<FakeTime>, scale: f64) -> Self {
+        let scale = scale.clamp(0.0, MAX_SCALE); // time can't run backwards
+        match fake_time {
+            Some(FakeTime::Absolute(start)) => Self::Simulated { start, anchor: Instant::now(), scale },
+            Some(FakeTime::Local(start)) => Self::Pending { start, scale },
+            None if scale == 1.0 => Self::System,
+            None => Self::Simulated { start: Utc::now(), anchor: Instant::now(), scale },
+        }
+    }
+
+    /// Starts a pending clock now, interpreting its start time in the given timezone. Returns whether the clock changed.
+    pub(crate) fn resolve<Z: TimeZone>(&mut self, timezone: &Z) -> bool {
+        let Self::Pending { start, scale } = *self else { return false };
+        // during a DST gap the local time doesn't exist, so fall back to reading it as UTC rather than refusing to start
+        let start = timezone.from_local_datetime(&start).earliest().map_or_else(|| start.and_utc(), |start| start.with_timezone(&Utc));
+        *self = Self::Simulated { start, anchor: Instant::now(), scale };
+        true
+    }
+
+    pub(crate) fn now(&self) -> DateTime<Utc> {
+        match *self {
+            Self::System | Self::Pending { .. } => Utc::now(),
+            // saturates instead of panicking if a fast clock has been running for so long that it's out of range
+            Self::Simulated { start, anchor, scale } => Duration::try_from_secs_f64(anchor.elapsed().as_secs_f64() * scale).ok()
+                .and_then(|elapsed| TimeDelta::from_std(elapsed).ok())
+                .and_then(|elapsed| start.checked_add_signed(elapsed))
+                .unwrap_or(DateTime::<Utc>::MAX_UTC),
+            Self::Fixed(now) => now,
+        }
+    }
+
+    /// The real instant at which this clock will have advanced by `duration` from now. `None` if it never will.
+    pub(crate) fn deadline(&self, duration: Duration) -> Option<Instant> {
+        match *self {
+            Self::System | Self::Pending { .. } => Some(Instant::now() + duration),
+            Self::Simulated { scale, .. } => if scale > 0.0 {
+                Instant::now().checked_add(Duration::try_from_secs_f64(duration.as_secs_f64() / scale).ok()?)
+            } else {
+                None
+            },
+            Self::Fixed(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).expect("invalid test time").with_timezone(&Utc)
+    }
+
+    #[test]
+    fn fixed() {
+        let clock = Clock::Fixed(time("2024-12-31T23:59:30+01:00"));
+        assert_eq!(clock.now(), time("2024-12-31T23:59:30+01:00"));
+        assert_eq!(clock.deadline(Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn simulated() {
+        let anchor = Instant::now() - Duration::from_secs(10);
+        let clock = Clock::Simulated { start: time("2024-12-31T23:59:00+01:00"), anchor, scale: 6.0 };
+        let elapsed = clock.now() - time("2024-12-31T23:59:00+01:00");
+        assert!(elapsed >= TimeDelta::seconds(60) && elapsed < TimeDelta::seconds(61), "unexpected elapsed time: {elapsed}");
+        let deadline = clock.deadline(Duration::from_secs(60)).expect("simulated clock stopped");
+        let real = deadline - Instant::now();
+        assert!(real > Duration::from_secs(9) && real <= Duration::from_secs(10), "unexpected real duration: {real:?}");
+    }
+
+    #[test]
+    fn stopped() {
+        let clock = Clock::Simulated { start: time("2024-12-31T23:59:00+01:00"), anchor: Instant::now(), scale: 0.0 };
+        assert_eq!(clock.now(), time("2024-12-31T23:59:00+01:00"));
+        assert_eq!(clock.deadline(Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn scale() {
+        assert_eq!(parse_scale("60").expect("failed to parse time scale"), 60.0);
+        assert_eq!(parse_scale("0").expect("failed to parse time scale"), 0.0);
+        for invalid in ["inf", "NaN", "-1", "1e9", "fast"] {
+            assert!(parse_scale(invalid).is_err(), "{invalid:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn out_of_range() {
+        let clock = Clock::Simulated { start: DateTime::<Utc>::MAX_UTC - TimeDelta::seconds(1), anchor: Instant::now() - Duration::from_secs(1), scale: MAX_SCALE };
+        assert_eq!(clock.now(), DateTime::<Utc>::MAX_UTC);
+        let clock = Clock::Simulated { start: time("2024-12-31T23:59:00+01:00"), anchor: Instant::now(), scale: f64::MIN_POSITIVE };
+        assert_eq!(clock.deadline(Duration::from_secs(1)), None);
+    }
+
+    #[test]
+    fn resolve_local() {
+        let mut clock = Clock::new(Some("2024-12-31T23:55".parse().expect("failed to parse fake time")), 0.0);
+        assert!(clock.resolve(&chrono_tz::Europe::Berlin));
+        assert_eq!(clock.now(), time("2024-12-31T23:55:00+01:00"));
+        assert!(!clock.resolve(&chrono_tz::America::New_York));
+    }
+
+    #[test]
+    fn absolute() {
+        let clock = Clock::new(Some("2024-12-31T23:55:00-05:00".parse().expect("failed to parse fake time")), 0.0);
+        assert_eq!(clock.now(), time("2025-01-01T04:55:00Z"));
+    }
+}
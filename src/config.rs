@@ -1,6 +1,40 @@
 use {
+    std::{
+        collections::HashMap,
+        convert::Infallible as Never,
+        fmt,
+        path::PathBuf,
+        sync::Arc,
+        time::{
+            Duration,
+            SystemTime,
+        },
+    },
+    chrono::prelude::*,
     serde::Deserialize,
+    tokio::{
+        sync::watch,
+        time::{
+            MissedTickBehavior,
+            interval,
+        },
+    },
+    url::Url,
     wheel::fs,
+    winit::event_loop::{
+        EventLoopClosed,
+        EventLoopProxy,
+    },
+    crate::{
+        UserEvent,
+        font::FontsConfig,
+        output::{
+            BurnIn,
+            Mirror,
+            Rotation,
+            SafeArea,
+        },
+    },
 };
 #[cfg(unix)] use xdg::BaseDirectories;
 #[cfg(windows)] use directories::ProjectDirs;
@@ -9,30 +43,465 @@ use {
 pub(crate) enum Error {
     #[error(transparent)] Wheel(#[from] wheel::Error),
     #[cfg(unix)]
-    #[error("config file missing, create at $XDG_CONFIG_DIRS/fidera/client-config.json")]
-    MissingConfig,
+    #[error("API key missing, add to config file at $XDG_CONFIG_DIRS/fidera/client-config.json")]
+    MissingApiKey,
+    #[cfg(windows)]
+    #[error("API key missing, add to config file at %APPDATA%\\Gefolge\\sil\\config\\client-config.json")]
+    MissingApiKey,
     #[cfg(windows)]
     #[error("user folder not found")]
     MissingHomeDir,
+    #[error("config file was deleted")]
+    Deleted,
 }
 
+/// How often the config file is checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
 #[derive(Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub(crate) struct Config {
-    pub(crate) api_key: String,
+    #[serde(default)]
+    api_key: Option<String>,
+    /// The WebSocket API of gefolge.org, or a test server.
+    #[serde(default = "default_ws_url")]
+    pub(crate) ws_url: String,
+    #[serde(default)]
+    pub(crate) theme: Theme,
+    #[serde(default)]
+    pub(crate) display: DisplayConfig,
+    #[serde(default)]
+    pub(crate) scheduler: SchedulerConfig,
+    #[serde(default)]
+    pub(crate) modes: ModesConfig,
+    #[serde(default)]
+    pub(crate) paths: PathsConfig,
+    #[serde(default)]
+    pub(crate) update: UpdateConfig,
+    #[serde(default)]
+    pub(crate) fonts: FontsConfig,
+    /// Approximate upper limit for the memory used by rasterized glyphs.
+    #[serde(default = "default_glyph_cache_budget_mib")]
+    pub(crate) glyph_cache_budget_mib: usize,
+    /// PNG or SVG. An SVG logo is rendered at the exact size needed for the current resolution.
+    #[serde(default = "default_logo_url")]
+    pub(crate) logo_url: String,
+    /// Additional images, e.g. per-event artwork, which can be used in text markup as `{icon=name}`.
+    #[serde(default)]
+    pub(crate) icons: HashMap<String, PathBuf>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Theme {
+    /// White text on black backgrounds.
+    #[default]
+    Dark,
+    /// Black text on mostly white backgrounds.
+    Light,
+}
+
+impl Theme {
+    /// Applies the `--light` and `--dark` command-line flags, which override the config file.
+    pub(crate) fn with_flags(self, light: bool, dark: bool) -> Self {
+        if light {
+            Self::Light
+        } else if dark {
+            Self::Dark
+        } else {
+            self
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct DisplayConfig {
+    /// Identifies this display to the server if an event has more than one, e.g. `"kitchen"`.
+    #[serde(default)]
+    pub(crate) id: Option<String>,
+    /// Lets the server target groups of displays, e.g. `["main"]` for the projector in the main room.
+    #[serde(default)]
+    pub(crate) roles: Vec<String>,
+    /// Insets from the screen edges for displays that crop the image, e.g. `"2%"` or `"20 40"`. Use `sil --calibrate` to find the right values.
+    #[serde(default)]
+    pub(crate) safe_area: SafeArea,
+    /// Clockwise rotation in degrees (0, 90, 180, or 270), e.g. 180 for a ceiling-mounted projector.
+    #[serde(default)]
+    pub(crate) rotate: Rotation,
+    /// `"none"`, `"horizontal"`, `"vertical"`, or `"both"`, e.g. horizontal for a rear-projection screen.
+    #[serde(default)]
+    pub(crate) mirror: Mirror,
+    /// Pixel shifting, periodic inversion, and overnight dimming for displays that are prone to burn-in.
+    #[serde(default)]
+    pub(crate) burn_in: BurnIn,
+    /// Render at a fraction of the native resolution (between 0.1 and 1) and scale up, for devices that are too slow to redraw at full resolution.
+    #[serde(default = "default_render_scale")]
+    pub(crate) render_scale: f32,
+}
+
+impl Default for DisplayConfig {
+    fn default() -> Self {
+        Self {
+            id: None,
+            roles: Vec::default(),
+            safe_area: SafeArea::default(),
+            rotate: Rotation::default(),
+            mirror: Mirror::default(),
+            burn_in: BurnIn::default(),
+            render_scale: default_render_scale(),
+        }
+    }
+}
+
+impl DisplayConfig {
+    /// Adds the display's id and roles to the WebSocket URL as query parameters, so the server knows which display it's talking to.
+    ///
+    /// The id is sent as a single `display` parameter and each role as a separate `role` parameter, e.g. `?display=kitchen&role=main&role=small`.
+    /// `ClientMessageV2` has no field for these, so a server that doesn't read the parameters ignores them and treats all displays the same.
+    //TODO send as part of the handshake once gefolge-web-lib supports it
+    pub(crate) fn ws_url(&self, base: &str) -> Result<Url, url::ParseError> {
+        let mut url = Url::parse(base)?;
+        if self.id.is_some() || !self.roles.is_empty() {
+            let mut query = url.query_pairs_mut();
+            if let Some(ref id) = self.id {
+                query.append_pair("display", id);
+            }
+            for role in &self.roles {
+                query.append_pair("role", role);
+            }
+        }
+        Ok(url)
+    }
+
+    /// The id and roles for the debug overlay.
+    pub(crate) fn description(&self) -> String {
+        let id = self.id.as_deref().unwrap_or("unnamed");
+        if self.roles.is_empty() {
+            id.to_owned()
+        } else {
+            format!("{id} (roles: {})", self.roles.join(", "))
+        }
+    }
+}
+
+fn default_render_scale() -> f32 { 1.0 }
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct SchedulerConfig {
+    /// How long each mode is shown before the next one is picked.
+    #[serde(default = "default_rotation_interval_secs")]
+    pub(crate) rotation_interval_secs: u64,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            rotation_interval_secs: default_rotation_interval_secs(),
+        }
+    }
+}
+
+fn default_rotation_interval_secs() -> u64 { 10 }
+
+/// Which modes the scheduler may pick, and when.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ModesConfig {
+    #[serde(default)]
+    pub(crate) binary_time: ModeConfig,
+    #[serde(default)]
+    pub(crate) close_windows: CloseWindowsConfig,
+    #[serde(default)]
+    pub(crate) hexagesimal_time: ModeConfig,
+    #[serde(default)]
+    pub(crate) new_year: NewYearConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ModeConfig {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+}
+
+impl Default for ModeConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct CloseWindowsConfig {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    /// Local time in the event's timezone at which the reminder starts, e.g. `"22:00:00"`.
+    #[serde(default = "default_close_windows_time")]
+    pub(crate) time: NaiveTime,
+    #[serde(default = "default_close_windows_duration_mins")]
+    pub(crate) duration_mins: u32,
+}
+
+impl Default for CloseWindowsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            time: default_close_windows_time(),
+            duration_mins: default_close_windows_duration_mins(),
+        }
+    }
+}
+
+fn default_close_windows_time() -> NaiveTime { NaiveTime::from_hms_opt(22, 0, 0).expect("invalid default time") }
+fn default_close_windows_duration_mins() -> u32 { 5 }
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct NewYearConfig {
+    #[serde(default = "default_true")]
+    pub(crate) enabled: bool,
+    /// For how long before and after midnight the New Year mode takes priority over other modes.
+    #[serde(default = "default_new_year_priority_mins")]
+    pub(crate) priority_mins: u32,
+}
+
+impl Default for NewYearConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            priority_mins: default_new_year_priority_mins(),
+        }
+    }
+}
+
+fn default_new_year_priority_mins() -> u32 { 60 }
+
+fn default_true() -> bool { true }
+
+/// Where sil is installed, for self-updates. Both default to paths derived from the running binary, see [`crate::install::InstallPaths`].
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct PathsConfig {
+    /// The binary that is normally started, e.g. by a systemd unit.
+    #[serde(default)]
+    pub(crate) bin: Option<PathBuf>,
+    /// Where updates are downloaded to. Defaults to `bin` with `-reiwa` appended to the file name.
+    #[serde(default)]
+    pub(crate) staged_bin: Option<PathBuf>,
+}
+
+/// The release URL compiled into official builds, used if `update.url` isn't configured.
+const DEFAULT_UPDATE_URL: Option<&str> = option_env!("SIL_UPDATE_URL");
+/// The project's minisign public key compiled into official builds, used if `update.publicKey` isn't configured.
+const DEFAULT_UPDATE_PUBLIC_KEY: Option<&str> = option_env!("SIL_UPDATE_PUBLIC_KEY");
+
+/// Where self-updates are downloaded from. If neither this nor the build specifies a URL, updates are copied from reiwa via `scp`, which requires SSH access.
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct UpdateConfig {
+    /// HTTPS URL of the release binary, with `{version}` replaced by the version to download. The minisign signature is expected at the same URL with `.minisig` appended.
+    #[serde(default)]
+    pub(crate) url: Option<String>,
+    /// The minisign public key that release binaries are signed with, e.g. `"RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3"`.
+    #[serde(default)]
+    pub(crate) public_key: Option<String>,
+}
+
+impl UpdateConfig {
+    /// The configured release URL, or the one compiled in via the `SIL_UPDATE_URL` environment variable.
+    pub(crate) fn url(&self) -> Option<&str> {
+        self.url.as_deref().or(DEFAULT_UPDATE_URL)
+    }
+
+    /// The configured public key, or the one compiled in via the `SIL_UPDATE_PUBLIC_KEY` environment variable.
+    pub(crate) fn public_key(&self) -> Option<&str> {
+        self.public_key.as_deref().or(DEFAULT_UPDATE_PUBLIC_KEY)
+    }
+}
+
+fn default_glyph_cache_budget_mib() -> usize { 32 }
+fn default_ws_url() -> String { "wss://gefolge.org/api/v2/websocket".to_owned() }
+fn default_logo_url() -> String { "https://gefolge.org/static/gefolge.png".to_owned() }
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            api_key: None,
+            ws_url: default_ws_url(),
+            theme: Theme::default(),
+            display: DisplayConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            modes: ModesConfig::default(),
+            paths: PathsConfig::default(),
+            update: UpdateConfig::default(),
+            fonts: FontsConfig::default(),
+            glyph_cache_budget_mib: default_glyph_cache_budget_mib(),
+            logo_url: default_logo_url(),
+            icons: HashMap::default(),
+        }
+    }
+}
+
+// manual impl to keep the API key out of error messages
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Self { api_key, ws_url, theme, display, scheduler, modes, paths, update, fonts, glyph_cache_budget_mib, logo_url, icons } = self;
+        f.debug_struct("Config")
+            .field("api_key", &api_key.as_ref().map(|_| "[redacted]"))
+            .field("ws_url", ws_url)
+            .field("theme", theme)
+            .field("display", display)
+            .field("scheduler", scheduler)
+            .field("modes", modes)
+            .field("paths", paths)
+            .field("update", update)
+            .field("fonts", fonts)
+            .field("glyph_cache_budget_mib", glyph_cache_budget_mib)
+            .field("logo_url", logo_url)
+            .field("icons", icons)
+            .finish()
+    }
 }
 
 impl Config {
-    pub(crate) async fn load() -> Result<Config, Error> {
+    /// The location of the config file, if it exists.
+    pub(crate) async fn path() -> Result<Option<PathBuf>, Error> {
         #[cfg(unix)] {
-            if let Some(config_path) = BaseDirectories::new().find_config_file("fidera/client-config.json") {
-                Ok(fs::read_json(config_path).await?)
-            } else {
-                Err(Error::MissingConfig)
-            }
+            Ok(BaseDirectories::new().find_config_file("fidera/client-config.json"))
         }
         #[cfg(windows)] {
-            Ok(fs::read_json(ProjectDirs::from("org", "Gefolge", "sil").ok_or(Error::MissingHomeDir)?.config_dir().join("client-config.json")).await?)
+            let config_path = ProjectDirs::from("org", "Gefolge", "sil").ok_or(Error::MissingHomeDir)?.config_dir().join("client-config.json");
+            Ok(fs::exists(&config_path).await?.then_some(config_path))
         }
     }
+
+    /// Loads the config file, or returns the default config if there is none.
+    pub(crate) async fn load() -> Result<Config, Error> {
+        if let Some(config_path) = Self::path().await? {
+            Ok(fs::read_json(config_path).await?)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    /// Loads only the `paths` section of the config file, ignoring errors in the rest of it. Falls back to the default paths if that fails too.
+    pub(crate) async fn load_paths() -> PathsConfig {
+        #[derive(Deserialize)]
+        struct PathsOnly {
+            #[serde(default)]
+            paths: PathsConfig,
+        }
+
+        match Self::path().await {
+            Ok(Some(config_path)) => fs::read_json::<PathsOnly>(config_path).await.map(|config| config.paths).unwrap_or_default(),
+            Ok(None) | Err(_) => PathsConfig::default(),
+        }
+    }
+
+    pub(crate) fn api_key(&self) -> Result<&str, Error> {
+        self.api_key.as_deref().ok_or(Error::MissingApiKey)
+    }
+
+    /// The fields which differ from the config in effect but are only read at startup, either by sil itself (`started`) or by the scheduler (`scheduler`).
+    ///
+    /// `wsUrl` is ignored if it's overridden by `--ws-url`, since changing it has no effect then.
+    pub(crate) fn restart_required(&self, started: &Config, scheduler: &Config, ws_url_overridden: bool) -> Vec<&'static str> {
+        // destructured so a new field can't be forgotten here
+        let Self { api_key, ws_url, theme: _, display, scheduler: _, modes: _, paths, update: _, fonts, glyph_cache_budget_mib, logo_url, icons } = self;
+        [
+            ("apiKey", *api_key != scheduler.api_key),
+            ("wsUrl", !ws_url_overridden && *ws_url != scheduler.ws_url),
+            ("display.id", display.id != scheduler.display.id),
+            ("display.roles", display.roles != scheduler.display.roles),
+            ("paths", *paths != started.paths),
+            ("fonts", *fonts != started.fonts),
+            ("glyphCacheBudgetMib", *glyph_cache_budget_mib != started.glyph_cache_budget_mib),
+            ("logoUrl", *logo_url != scheduler.logo_url),
+            ("icons", *icons != scheduler.icons),
+        ].into_iter().filter_map(|(field, changed)| changed.then_some(field)).collect()
+    }
+}
+
+/// Identifies the current version of the config file without reading it.
+async fn file_version() -> Option<(PathBuf, SystemTime)> {
+    let path = Config::path().await.ok()??;
+    let modified = fs::metadata(&path).await.ok()?.modified().ok()?;
+    Some((path, modified))
+}
+
+/// Reloads the config file whenever it changes, and passes valid configs on to the scheduler and the event loop.
+///
+/// An invalid or deleted config is reported on screen and otherwise ignored, so a mistake made while editing the file during an event doesn't take down the display.
+///
+/// If the config file was invalid at startup (`config_valid` is false), the scheduler only starts once it has been fixed, so it reads the fixed version.
+pub(crate) async fn watch(config_tx: watch::Sender<Arc<Config>>, config_valid: bool, ws_url_overridden: bool, states_tx: EventLoopProxy<UserEvent>) -> Result<Never, EventLoopClosed<UserEvent>> {
+    let initial_config = config_tx.borrow().clone();
+    let mut scheduler_config = config_valid.then(|| initial_config.clone());
+    let mut version = file_version().await;
+    let mut interval = interval(POLL_INTERVAL);
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        interval.tick().await;
+        let new_version = file_version().await;
+        if new_version == version { continue }
+        version = new_version;
+        // loaded from the path that was just checked rather than via Config::load, since falling back to the default config would drop the API key
+        let Some((path, _)) = &version else {
+            tokio::task::block_in_place(|| states_tx.send_event(UserEvent::ConfigError(Error::Deleted)))?;
+            continue
+        };
+        match fs::read_json::<Config>(path).await {
+            Ok(config) => {
+                let config = Arc::new(config);
+                let scheduler_config = scheduler_config.get_or_insert_with(|| config.clone());
+                let restart_required = config.restart_required(&initial_config, scheduler_config, ws_url_overridden);
+                config_tx.send_replace(config.clone());
+                tokio::task::block_in_place(|| states_tx.send_event(UserEvent::Config(config, restart_required)))?;
+            }
+            Err(e) => {
+                // the file may have been deleted since it was checked
+                let e = if fs::exists(path).await.unwrap_or(true) { Error::from(e) } else { Error::Deleted };
+                tokio::task::block_in_place(|| states_tx.send_event(UserEvent::ConfigError(e)))?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ws_url() {
+        let mut display = DisplayConfig::default();
+        assert_eq!(display.ws_url("wss://gefolge.org/api/v2/websocket").expect("invalid URL").as_str(), "wss://gefolge.org/api/v2/websocket");
+        display.id = Some("Küche 2".to_owned());
+        display.roles = vec!["kitchen".to_owned(), "small".to_owned()];
+        assert_eq!(display.ws_url("wss://gefolge.org/api/v2/websocket").expect("invalid URL").as_str(), "wss://gefolge.org/api/v2/websocket?display=K%C3%BCche+2&role=kitchen&role=small");
+        assert_eq!(display.ws_url("ws://localhost:24823/websocket?debug=1").expect("invalid URL").as_str(), "ws://localhost:24823/websocket?debug=1&display=K%C3%BCche+2&role=kitchen&role=small");
+    }
+
+    #[test]
+    fn restart_required() {
+        let running = Config::default();
+        let mut config = Config::default();
+        assert!(config.restart_required(&running, &running, false).is_empty());
+        config.theme = Theme::Light;
+        config.display.render_scale = 0.5;
+        config.scheduler.rotation_interval_secs = 30;
+        assert!(config.restart_required(&running, &running, false).is_empty());
+        config.display.id = Some("kitchen".to_owned());
+        config.fonts.bold.family = "Noto Sans".to_owned();
+        assert_eq!(config.restart_required(&running, &running, false), ["display.id", "fonts"]);
+        // the scheduler started late with the edited config
+        assert_eq!(config.restart_required(&running, &config, false), ["fonts"]);
+        config.ws_url = "ws://localhost:24823/websocket".to_owned();
+        assert_eq!(config.restart_required(&running, &running, false), ["wsUrl", "display.id", "fonts"]);
+        // overridden by `--ws-url`
+        assert_eq!(config.restart_required(&running, &running, true), ["display.id", "fonts"]);
+    }
 }
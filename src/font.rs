@@ -0,0 +1,241 @@
+use {
+    std::{
+        io,
+        path::{
+            Path,
+            PathBuf,
+        },
+    },
+    fontdue::FontSettings,
+    serde::Deserialize,
+    wheel::fs,
+};
+#[cfg(unix)] use tokio::process::Command;
+
+const BUNDLED_REGULAR: &[u8] = include_bytes!("../assets/fonts/DejaVuSans.ttf");
+const BUNDLED_BOLD: &[u8] = include_bytes!("../assets/fonts/DejaVuSans-Bold.ttf");
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum Error {
+    #[error(transparent)] Io(#[from] io::Error),
+    #[error(transparent)] Wheel(#[from] wheel::Error),
+    #[error("failed to load font from {}: {msg}", path.display())]
+    Parse {
+        path: PathBuf,
+        msg: &'static str,
+    },
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) enum Weight {
+    Light,
+    #[default]
+    Regular,
+    Bold,
+}
+
+impl Weight {
+    /// The style name used in font file names, e.g. `Bold` in `DejaVuSans-Bold.ttf`.
+    fn file_suffix(&self) -> Option<&'static str> {
+        match self {
+            Self::Light => Some("Light"),
+            Self::Regular => None,
+            Self::Bold => Some("Bold"),
+        }
+    }
+
+    #[cfg(unix)]
+    fn fontconfig_name(&self) -> &'static str {
+        match self {
+            Self::Light => "light",
+            Self::Regular => "regular",
+            Self::Bold => "bold",
+        }
+    }
+}
+
+/// Where to find one of the fonts used by sil.
+///
+/// If `path` is given, that file is used. Otherwise, the font is looked up by `family` and `weight` in the system's fonts,
+/// and if that fails, the DejaVu Sans font compiled into the binary is used.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FontConfig {
+    #[serde(default)]
+    pub(crate) path: Option<PathBuf>,
+    #[serde(default = "default_family")]
+    pub(crate) family: String,
+    #[serde(default)]
+    pub(crate) weight: Weight,
+}
+
+fn default_family() -> String { "DejaVu Sans".to_owned() }
+
+impl FontConfig {
+    fn family(weight: Weight) -> Self {
+        Self {
+            path: None,
+            family: default_family(),
+            weight,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct FontsConfig {
+    /// Used for most text.
+    #[serde(default = "default_regular")]
+    pub(crate) regular: FontConfig,
+    /// Used for emphasized text.
+    #[serde(default = "default_bold")]
+    pub(crate) bold: FontConfig,
+    /// Used for large numbers, such as the New Year countdown.
+    #[serde(default = "default_regular")]
+    pub(crate) display: FontConfig,
+}
+
+fn default_regular() -> FontConfig { FontConfig::family(Weight::Regular) }
+fn default_bold() -> FontConfig { FontConfig::family(Weight::Bold) }
+
+impl Default for FontsConfig {
+    fn default() -> Self {
+        Self {
+            regular: default_regular(),
+            bold: default_bold(),
+            display: default_regular(),
+        }
+    }
+}
+
+/// Selects one of the fonts in [`Fonts`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Face {
+    // the discriminants are the indices into the font list used by `markup`
+    Regular,
+    Bold,
+    Display,
+}
+
+pub(crate) struct Fonts {
+    pub(crate) regular: fontdue::Font,
+    pub(crate) bold: fontdue::Font,
+    pub(crate) display: fontdue::Font,
+}
+
+impl Fonts {
+    pub(crate) async fn load(config: &FontsConfig) -> Result<Self, Error> {
+        Ok(Self {
+            regular: load(&config.regular).await?,
+            bold: load(&config.bold).await?,
+            display: load(&config.display).await?,
+        })
+    }
+
+    /// Only the fonts compiled into the binary, for output that doesn't depend on the fonts installed on the system.
+    #[cfg(test)]
+    pub(crate) fn bundled() -> Self {
+        let regular = parse(BUNDLED_REGULAR.to_vec(), Path::new("<bundled>")).expect("failed to parse bundled font");
+        Self {
+            bold: parse(BUNDLED_BOLD.to_vec(), Path::new("<bundled>")).expect("failed to parse bundled font"),
+            display: regular.clone(),
+            regular,
+        }
+    }
+}
+
+fn parse(data: Vec<u8>, path: &Path) -> Result<fontdue::Font, Error> {
+    fontdue::Font::from_bytes(data, FontSettings {
+        scale: 100.0,
+        ..FontSettings::default()
+    }).map_err(|msg| Error::Parse { path: path.to_owned(), msg })
+}
+
+pub(crate) async fn load(config: &FontConfig) -> Result<fontdue::Font, Error> {
+    if let Some(ref path) = config.path {
+        return parse(fs::read(path).await?, path)
+    }
+    if let Some(path) = find(&config.family, config.weight).await? {
+        return parse(fs::read(&path).await?, &path)
+    }
+    eprintln!("font {:?} ({:?}) not found, using bundled DejaVu Sans", config.family, config.weight);
+    parse(match config.weight {
+        Weight::Light | Weight::Regular => BUNDLED_REGULAR,
+        Weight::Bold => BUNDLED_BOLD,
+    }.to_vec(), Path::new("<bundled>"))
+}
+
+/// Looks up a font by family name and weight, first using fontconfig (if available) and then by scanning the usual font directories.
+pub(crate) async fn find(family: &str, weight: Weight) -> Result<Option<PathBuf>, Error> {
+    #[cfg(unix)] {
+        if let Ok(output) = Command::new("fc-match").arg("--format=%{family}\n%{file}").arg(format!("{family}:weight={}", weight.fontconfig_name())).output().await {
+            // fc-match always returns its best guess, so check that the family actually matches
+            if output.status.success() {
+                if let Some((families, file)) = String::from_utf8_lossy(&output.stdout).split_once('\n') {
+                    if families.split(',').any(|iter_family| iter_family.eq_ignore_ascii_case(family)) {
+                        return Ok(Some(PathBuf::from(file)))
+                    }
+                }
+            }
+        }
+    }
+    let file_stem = match weight.file_suffix() {
+        Some(suffix) => format!("{}-{suffix}", family.replace(' ', "")),
+        None => family.replace(' ', ""),
+    };
+    Ok(tokio::task::block_in_place(|| font_dirs().into_iter().find_map(|dir| find_in_dir(&dir, &file_stem))))
+}
+
+fn font_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::default();
+    #[cfg(unix)] {
+        dirs.extend(xdg::BaseDirectories::new().get_data_home().map(|data_home| data_home.join("fonts")));
+        dirs.push(PathBuf::from("/run/current-system/sw/share/X11/fonts")); // NixOS
+        dirs.push(PathBuf::from("/usr/local/share/fonts"));
+        dirs.push(PathBuf::from("/usr/share/fonts"));
+    }
+    #[cfg(target_os = "macos")] {
+        dirs.extend(std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Fonts")));
+        dirs.push(PathBuf::from("/Library/Fonts"));
+    }
+    #[cfg(windows)] {
+        dirs.extend(std::env::var_os("LOCALAPPDATA").map(|local_app_data| PathBuf::from(local_app_data).join("Microsoft").join("Windows").join("Fonts")));
+        dirs.push(PathBuf::from("\\Windows\\Fonts"));
+    }
+    dirs
+}
+
+/// Searches a font directory recursively. Directories that can't be read are skipped, and symlinked directories aren't followed to avoid cycles.
+fn find_in_dir(dir: &Path, file_stem: &str) -> Option<PathBuf> {
+    for entry in std::fs::read_dir(dir).ok()? {
+        let Ok(entry) = entry else { continue };
+        let Ok(file_type) = entry.file_type() else { continue };
+        let path = entry.path();
+        if file_type.is_dir() {
+            if let Some(path) = find_in_dir(&path, file_stem) {
+                return Some(path)
+            }
+        } else if path.file_stem().is_some_and(|iter_stem| iter_stem.eq_ignore_ascii_case(file_stem)) && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("ttf") || ext.eq_ignore_ascii_case("otf")) {
+            return Some(path)
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn symlink_cycle() {
+        let dir = tempfile::TempDir::new().expect("failed to create temp dir");
+        std::fs::create_dir(dir.path().join("truetype")).expect("failed to create test dir");
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("truetype").join("loop")).expect("failed to create symlink");
+        std::fs::write(dir.path().join("truetype").join("DejaVuSans-Bold.ttf"), BUNDLED_BOLD).expect("failed to write test file");
+        assert_eq!(find_in_dir(dir.path(), "DejaVuSans-Bold"), Some(dir.path().join("truetype").join("DejaVuSans-Bold.ttf")));
+        assert_eq!(find_in_dir(dir.path(), "DejaVuSans"), None);
+        assert_eq!(find_in_dir(&dir.path().join("missing"), "DejaVuSans"), None);
+    }
+}
@@ -0,0 +1,167 @@
+use {
+    std::collections::HashMap,
+    fontdue::layout::GlyphRasterConfig,
+    tiny_skia::{
+        ColorU8,
+        Pixmap,
+    },
+};
+
+type Key = (GlyphRasterConfig, [u8; 4]); // ColorU8 does not implement Eq or Hash
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Stats {
+    pub(crate) entries: usize,
+    pub(crate) bytes: usize,
+    pub(crate) hits: u64,
+    pub(crate) misses: u64,
+    pub(crate) evictions: u64,
+}
+
+/// A cache of rasterized, colored glyphs with a memory budget, evicting the least recently used glyphs once the budget is exceeded.
+pub(crate) struct GlyphCache {
+    /// `None` for glyphs that are empty, e.g. spaces, so they aren't rasterized again on every frame.
+    glyphs: HashMap<Key, (Option<Pixmap>, u64)>,
+    frame: u64,
+    budget: usize,
+    bytes: usize,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl GlyphCache {
+    pub(crate) fn new(budget: usize) -> Self {
+        Self {
+            glyphs: HashMap::default(),
+            frame: 0,
+            bytes: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+            budget,
+        }
+    }
+
+    /// Glyphs used during the current frame are never evicted.
+    pub(crate) fn begin_frame(&mut self) {
+        self.frame += 1;
+    }
+
+    /// Returns the cached glyph, rasterizing it if it's not in the cache. Returns `None` for empty glyphs.
+    pub(crate) fn get(&mut self, config: GlyphRasterConfig, color: ColorU8, rasterize: impl FnOnce() -> Option<Pixmap>) -> Option<&Pixmap> {
+        let key = (config, [color.red(), color.green(), color.blue(), color.alpha()]);
+        if let Some((_, last_used)) = self.glyphs.get_mut(&key) {
+            self.hits += 1;
+            *last_used = self.frame;
+        } else {
+            self.misses += 1;
+            let pixmap = rasterize();
+            self.bytes += pixmap.as_ref().map_or(0, pixmap_bytes);
+            self.glyphs.insert(key, (pixmap, self.frame));
+            self.evict();
+        }
+        self.glyphs.get(&key).and_then(|(pixmap, _)| pixmap.as_ref())
+    }
+
+    fn evict(&mut self) {
+        if self.bytes <= self.budget { return }
+        let mut by_age = self.glyphs.iter().filter(|&(_, &(_, last_used))| last_used < self.frame).map(|(&key, &(_, last_used))| (last_used, key)).collect::<Vec<_>>();
+        by_age.sort_unstable_by_key(|&(last_used, _)| last_used);
+        // evict down to 3/4 of the budget so we don't have to evict again on the next new glyph
+        let target = self.budget / 4 * 3;
+        for (_, key) in by_age {
+            if self.bytes <= target { break }
+            if let Some((pixmap, _)) = self.glyphs.remove(&key) {
+                self.bytes -= pixmap.as_ref().map_or(0, pixmap_bytes);
+                self.evictions += 1;
+            }
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Stats {
+        Stats {
+            entries: self.glyphs.len(),
+            bytes: self.bytes,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+}
+
+fn pixmap_bytes(pixmap: &Pixmap) -> usize {
+    pixmap.data().len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 10×10 glyph, which takes up 400 bytes.
+    fn glyph(glyph_index: u16) -> (GlyphRasterConfig, ColorU8) {
+        (GlyphRasterConfig { glyph_index, px: 10.0, font_hash: 0 }, ColorU8::from_rgba(0xff, 0xff, 0xff, 0xff))
+    }
+
+    fn rasterize() -> Option<Pixmap> {
+        Pixmap::new(10, 10)
+    }
+
+    #[test]
+    fn hits_and_misses() {
+        let mut cache = GlyphCache::new(1000);
+        let (config, color) = glyph(1);
+        cache.begin_frame();
+        assert!(cache.get(config, color, rasterize).is_some());
+        assert!(cache.get(config, color, || panic!("cached glyph rasterized again")).is_some());
+        // the same glyph in a different color is a different entry
+        assert!(cache.get(config, ColorU8::from_rgba(0, 0, 0, 0xff), rasterize).is_some());
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.hits, stats.misses), (2, 800, 1, 2));
+    }
+
+    #[test]
+    fn empty_glyphs_are_cached() {
+        let mut cache = GlyphCache::new(1000);
+        let (config, color) = glyph(1);
+        cache.begin_frame();
+        assert!(cache.get(config, color, || None).is_none());
+        assert!(cache.get(config, color, || panic!("empty glyph rasterized again")).is_none());
+        let stats = cache.stats();
+        assert_eq!((stats.bytes, stats.hits, stats.misses), (0, 1, 1));
+    }
+
+    #[test]
+    fn least_recently_used_are_evicted() {
+        let mut cache = GlyphCache::new(1100);
+        for glyph_index in 0..2 {
+            cache.begin_frame();
+            let (config, color) = glyph(glyph_index);
+            cache.get(config, color, rasterize);
+        }
+        // glyph 0 is used again, so glyph 1 is now the least recently used
+        cache.begin_frame();
+        let (config, color) = glyph(0);
+        cache.get(config, color, rasterize);
+        cache.begin_frame();
+        let (config, color) = glyph(2);
+        cache.get(config, color, rasterize);
+        // over budget, so glyphs are evicted until at most 825 bytes are used
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (2, 800, 1));
+        let (config, color) = glyph(0);
+        assert!(cache.get(config, color, || panic!("recently used glyph was evicted")).is_some());
+    }
+
+    #[test]
+    fn current_frame_is_never_evicted() {
+        let mut cache = GlyphCache::new(1000);
+        cache.begin_frame();
+        for glyph_index in 0..4 {
+            let (config, color) = glyph(glyph_index);
+            cache.get(config, color, rasterize);
+        }
+        let stats = cache.stats();
+        assert_eq!((stats.entries, stats.bytes, stats.evictions), (4, 1600, 0));
+    }
+}
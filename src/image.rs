@@ -0,0 +1,82 @@
+use {
+    resvg::usvg,
+    tiny_skia::*,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum Error {
+    #[error(transparent)] Png(#[from] png::DecodingError),
+    #[error(transparent)] Svg(#[from] usvg::Error),
+}
+
+#[derive(Debug)]
+enum Source {
+    Raster(Pixmap),
+    Vector(Box<usvg::Tree>),
+}
+
+/// A PNG or SVG image, e.g. the Gefolge logo. SVG images are rendered at the exact size they're drawn at.
+#[derive(Debug)]
+pub(crate) struct Image {
+    source: Source,
+    rendered: Option<Pixmap>,
+}
+
+impl Image {
+    pub(crate) fn decode(data: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            source: if data.starts_with(b"\x89PNG") {
+                Source::Raster(Pixmap::decode_png(data)?)
+            } else {
+                Source::Vector(Box::new(usvg::Tree::from_data(data, &usvg::Options::default())?))
+            },
+            rendered: None,
+        })
+    }
+
+    /// The natural size of the image.
+    pub(crate) fn size(&self) -> (f32, f32) {
+        match self.source {
+            Source::Raster(ref pixmap) => (pixmap.width() as f32, pixmap.height() as f32),
+            Source::Vector(ref tree) => (tree.size().width(), tree.size().height()),
+        }
+    }
+
+    pub(crate) fn is_vector(&self) -> bool {
+        matches!(self.source, Source::Vector(_))
+    }
+
+    /// Returns the image scaled to the given size. The result is cached until a different size is requested.
+    pub(crate) fn render(&mut self, width: u32, height: u32) -> Option<&Pixmap> {
+        if let Source::Raster(ref pixmap) = self.source {
+            if pixmap.width() == width && pixmap.height() == height {
+                return Some(pixmap)
+            }
+        }
+        if !self.rendered.as_ref().is_some_and(|rendered| rendered.width() == width && rendered.height() == height) {
+            let mut rendered = Pixmap::new(width, height)?;
+            let (natural_width, natural_height) = self.size();
+            let transform = Transform::from_scale(width as f32 / natural_width, height as f32 / natural_height);
+            match self.source {
+                Source::Raster(ref pixmap) => rendered.draw_pixmap(0, 0, pixmap.as_ref(), &PixmapPaint { quality: FilterQuality::Bicubic, ..PixmapPaint::default() }, transform, None),
+                Source::Vector(ref tree) => resvg::render(tree, transform, &mut rendered.as_mut()),
+            }
+            self.rendered = Some(rendered);
+        }
+        self.rendered.as_ref()
+    }
+
+    /// Draws the image centered in the given box, scaled to fit. Raster images are not scaled beyond their natural size to avoid blurriness.
+    pub(crate) fn draw_fit(&mut self, canvas: &mut PixmapMut<'_>, x: f32, y: f32, width: f32, height: f32) {
+        let (natural_width, natural_height) = self.size();
+        let mut scale = (width / natural_width).min(height / natural_height);
+        if !self.is_vector() {
+            scale = scale.min(1.0);
+        }
+        let scaled_width = (natural_width * scale).round();
+        let scaled_height = (natural_height * scale).round();
+        if let Some(pixmap) = self.render(scaled_width as u32, scaled_height as u32) {
+            canvas.draw_pixmap((x + (width - scaled_width) / 2.0) as i32, (y + (height - scaled_height) / 2.0) as i32, pixmap.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+        }
+    }
+}
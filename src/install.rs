@@ -0,0 +1,531 @@
+//! Where sil is installed, downloading updates, handing off from a freshly downloaded update to the installed binary, and rolling back updates that don't work.
+
+use {
+    std::{
+        ffi::OsString,
+        path::{
+            Path,
+            PathBuf,
+        },
+    },
+    minisign_verify::PublicKey,
+    crate::config::PathsConfig,
+};
+#[cfg(unix)] use {
+    std::time::Duration,
+    chrono::{
+        TimeDelta,
+        prelude::*,
+    },
+    semver::Version,
+    serde::{
+        Deserialize,
+        Serialize,
+    },
+    wheel::fs,
+};
+#[cfg(all(not(feature = "nixos"), unix))] use {
+    std::{
+        fs::Permissions,
+        os::unix::fs::PermissionsExt as _,
+    },
+    wheel::traits::{
+        IoResultExt as _,
+        ReqwestResponseExt as _,
+    },
+    crate::Error,
+};
+
+/// Appended to the file name of the installed binary to get the path where updates are downloaded to.
+const STAGED_SUFFIX: &str = "-reiwa";
+
+/// How long a freshly installed update has to run without errors before it's no longer rolled back.
+#[cfg(unix)] pub(crate) const TRIAL_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// How long a version that was rolled back is skipped before installing it is attempted again, e.g. in case the rollback was caused by a network outage.
+#[cfg(unix)] const RETRY_AFTER: TimeDelta = TimeDelta::days(1);
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct InstallPaths {
+    /// The binary that is normally started, e.g. by a systemd unit.
+    pub(crate) bin: PathBuf,
+    /// Where updates are downloaded to. They replace `bin` when they first run.
+    pub(crate) staged_bin: PathBuf,
+}
+
+impl InstallPaths {
+    /// Uses the paths from the config if given, and otherwise derives them from the running binary:
+    /// if its file name ends in `-reiwa`, it's the staged update and the installed binary is next to it without the suffix.
+    pub(crate) fn new(config: &PathsConfig, current_exe: &Path) -> Self {
+        let bin = config.bin.clone().unwrap_or_else(|| {
+            if let Some(name) = current_exe.file_name().and_then(|name| name.to_str()).and_then(|name| name.strip_suffix(STAGED_SUFFIX)) {
+                current_exe.with_file_name(name)
+            } else {
+                current_exe.to_owned()
+            }
+        });
+        let staged_bin = config.staged_bin.clone().unwrap_or_else(|| with_suffix(&bin, STAGED_SUFFIX));
+        Self { bin, staged_bin }
+    }
+
+    /// The binary that was installed before the current one, for rolling back.
+    #[cfg(unix)]
+    fn previous_bin(&self) -> PathBuf {
+        with_suffix(&self.bin, ".previous")
+    }
+
+    /// Exists while the installed binary is an update that hasn't run successfully yet, see [`Pending`].
+    #[cfg(unix)]
+    fn pending_marker(&self) -> PathBuf {
+        with_suffix(&self.bin, ".pending")
+    }
+
+    /// Records the last version that was rolled back, see [`Failed`].
+    #[cfg(unix)]
+    fn failed_marker(&self) -> PathBuf {
+        with_suffix(&self.bin, ".failed")
+    }
+
+    /// The binary to run after an update has been downloaded.
+    #[cfg(all(not(feature = "nixos"), unix))]
+    pub(crate) fn restart_target(&self, current_exe: &Path) -> &Path {
+        if current_exe == self.bin && self.staged_bin.exists() { &self.staged_bin } else { &self.bin }
+    }
+}
+
+/// Accepts either just the key or the contents of a minisign `.pub` file.
+pub(crate) fn parse_public_key(public_key: &str) -> Result<PublicKey, minisign_verify::Error> {
+    let public_key = public_key.trim();
+    if public_key.contains('\n') { PublicKey::decode(public_key) } else { PublicKey::from_base64(public_key) }
+}
+
+#[cfg(all(unix, any(test, not(feature = "nixos"))))]
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum VerifyError {
+    #[error(transparent)] Minisign(#[from] minisign_verify::Error),
+    #[error("signed for {found:?} instead of {expected:?}")]
+    VersionMismatch {
+        expected: String,
+        found: String,
+    },
+}
+
+/// Checks a downloaded update against its detached minisign signature, whose trusted comment has to name the expected version.
+///
+/// Without the version check, a validly signed older release could be served in place of the requested one.
+#[cfg(all(unix, any(test, not(feature = "nixos"))))]
+pub(crate) fn verify(public_key: &str, data: &[u8], signature: &str, version: &Version) -> Result<(), VerifyError> {
+    let signature = minisign_verify::Signature::decode(signature)?;
+    // legacy signatures don't cover a hash of the whole file, so only prehashed ones are accepted
+    parse_public_key(public_key)?.verify(data, &signature, false)?;
+    let expected = format!("sil {version}");
+    if signature.trusted_comment() == expected {
+        Ok(())
+    } else {
+        Err(VerifyError::VersionMismatch { expected, found: signature.trusted_comment().to_owned() })
+    }
+}
+
+/// Downloads the given version of sil over HTTPS and moves it to the staged path once its signature has been verified.
+#[cfg(all(not(feature = "nixos"), unix))]
+pub(crate) async fn download(http_client: &reqwest::Client, url_template: &str, public_key: &str, version: &Version, staged_bin: &Path) -> Result<(), Error> {
+    let url = url_template.replace("{version}", &version.to_string());
+    let data = http_client.get(&url)
+        .send().await?
+        .detailed_error_for_status().await?
+        .bytes().await?;
+    let signature = http_client.get(format!("{url}.minisig"))
+        .send().await?
+        .detailed_error_for_status().await?
+        .text().await?;
+    verify(public_key, &data, &signature, version)?;
+    // write to a temporary file first so an interrupted download never leaves a partial binary at the staged path
+    let partial = with_suffix(staged_bin, ".partial");
+    fs::write(&partial, &data).await?;
+    tokio::fs::set_permissions(&partial, Permissions::from_mode(0o755)).await.at(&partial)?;
+    fs::rename(&partial, staged_bin).await?;
+    Ok(())
+}
+
+/// The version of the running binary.
+#[cfg(unix)]
+pub(crate) fn current_version() -> Version {
+    env!("CARGO_PKG_VERSION").parse().expect("failed to parse package version")
+}
+
+/// Tracks an update from being installed until it has run successfully for [`TRIAL_PERIOD`].
+#[cfg(unix)]
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Pending {
+    version: Version,
+    state: PendingState,
+    /// The boot during which the update was started, to tell a crash from a power cycle.
+    #[serde(default)]
+    boot_id: Option<String>,
+}
+
+#[cfg(unix)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum PendingState {
+    /// Copied to the installed path but not started from there yet, or stopped cleanly since.
+    Installed,
+    /// Started from the installed path. If it's started again before the trial period is over, the previous run must have crashed.
+    Started,
+}
+
+/// A version that was rolled back. It's skipped by self-updates for [`RETRY_AFTER`].
+#[cfg(unix)]
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Failed {
+    version: Version,
+    /// Whether the rollback has been shown on screen.
+    reported: bool,
+    /// Missing in markers written by older versions, which are retried right away.
+    #[serde(default)]
+    rolled_back_at: Option<DateTime<Utc>>,
+}
+
+#[cfg(unix)]
+async fn read_marker<T: serde::de::DeserializeOwned>(path: &Path) -> Result<Option<T>, wheel::Error> {
+    Ok(if fs::exists(path).await? { Some(fs::read_json(path).await?) } else { None })
+}
+
+/// Identifies the current boot of the system. Only available on Linux.
+#[cfg(unix)]
+pub(crate) async fn boot_id() -> Option<String> {
+    fs::read_to_string("/proc/sys/kernel/random/boot_id").await.ok().map(|boot_id| boot_id.trim().to_owned())
+}
+
+#[cfg(unix)]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Handoff {
+    /// The installed binary has been replaced (by the staged update or by a rollback) and should be run instead.
+    Restart,
+    /// This is a staged update that was rolled back recently. The installed binary should be run instead, with self-updates disabled,
+    /// since it's a version that doesn't check for rollbacks and would otherwise download this update again right away.
+    Refused,
+    Continue,
+}
+
+/// Runs at startup. Installs the staged update if it's the running binary (keeping the previous binary for rollbacks),
+/// cleans up after an update has been installed, and rolls back an update that already failed to start once.
+///
+/// A restart during the trial period counts as a failure unless it was preceded by a clean shutdown (see [`stopped`]) or the system was rebooted in between.
+#[cfg(unix)]
+pub(crate) async fn handoff(paths: &InstallPaths, current_exe: &Path, version: &Version, boot_id: Option<String>) -> Result<Handoff, wheel::Error> {
+    if current_exe == paths.staged_bin {
+        if is_skipped(paths, version).await? {
+            // sil versions before 2.0.29 don't know about rollbacks and download the failed version again
+            fs::remove_file(&paths.staged_bin).await?;
+            return Ok(Handoff::Refused)
+        }
+        if fs::exists(&paths.bin).await? {
+            fs::copy(&paths.bin, paths.previous_bin()).await?;
+        }
+        fs::copy(&paths.staged_bin, &paths.bin).await?;
+        fs::write_json(paths.pending_marker(), Pending { version: version.clone(), state: PendingState::Installed, boot_id: None }).await?;
+        Ok(Handoff::Restart)
+    } else if current_exe == paths.bin {
+        if fs::exists(&paths.staged_bin).await? {
+            fs::remove_file(&paths.staged_bin).await?;
+        }
+        match read_marker::<Pending>(&paths.pending_marker()).await? {
+            Some(Pending { version: ref pending_version, state: PendingState::Installed, .. }) if pending_version == version => {
+                fs::write_json(paths.pending_marker(), Pending { version: version.clone(), state: PendingState::Started, boot_id }).await?;
+                Ok(Handoff::Continue)
+            }
+            Some(Pending { version: ref pending_version, state: PendingState::Started, boot_id: Some(ref started_boot_id) }) if pending_version == version && boot_id.as_ref() != Some(started_boot_id) => {
+                // the system was rebooted during the trial period, e.g. by a power cut, so the previous run didn't necessarily crash
+                fs::write_json(paths.pending_marker(), Pending { version: version.clone(), state: PendingState::Started, boot_id }).await?;
+                Ok(Handoff::Continue)
+            }
+            Some(Pending { version: ref pending_version, state: PendingState::Started, .. }) if pending_version == version => Ok(if rollback(paths).await? {
+                Handoff::Restart
+            } else {
+                Handoff::Continue
+            }),
+            Some(_) => {
+                // left over from a different version, e.g. after a manual reinstall
+                fs::remove_file(paths.pending_marker()).await?;
+                Ok(Handoff::Continue)
+            }
+            None => Ok(Handoff::Continue),
+        }
+    } else {
+        Ok(Handoff::Continue)
+    }
+}
+
+/// Whether the installed binary is an update that's still in its trial period.
+#[cfg(unix)]
+pub(crate) async fn is_pending(paths: &InstallPaths) -> Result<bool, wheel::Error> {
+    fs::exists(paths.pending_marker()).await
+}
+
+/// Called on a clean shutdown, so the next start of an update that's still in its trial period isn't mistaken for a crash.
+#[cfg(unix)]
+pub(crate) async fn stopped(paths: &InstallPaths) -> Result<(), wheel::Error> {
+    if let Some(Pending { version, state: PendingState::Started, .. }) = read_marker(&paths.pending_marker()).await? {
+        fs::write_json(paths.pending_marker(), Pending { version, state: PendingState::Installed, boot_id: None }).await?;
+    }
+    Ok(())
+}
+
+/// Ends the trial period of the installed update after it has run successfully.
+#[cfg(unix)]
+pub(crate) async fn confirm(paths: &InstallPaths) -> Result<(), wheel::Error> {
+    for marker in [paths.pending_marker(), paths.failed_marker()] {
+        if fs::exists(&marker).await? {
+            fs::remove_file(marker).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Restores the previous binary if the installed one is an update that's still in its trial period. Returns whether it did.
+#[cfg(unix)]
+pub(crate) async fn rollback(paths: &InstallPaths) -> Result<bool, wheel::Error> {
+    let Some(pending) = read_marker::<Pending>(&paths.pending_marker()).await? else { return Ok(false) };
+    let rolled_back = if fs::exists(paths.previous_bin()).await? {
+        // renamed rather than copied since the installed binary may be running
+        fs::rename(paths.previous_bin(), &paths.bin).await?;
+        fs::write_json(paths.failed_marker(), Failed { version: pending.version, reported: false, rolled_back_at: Some(Utc::now()) }).await?;
+        true
+    } else {
+        false
+    };
+    fs::remove_file(paths.pending_marker()).await?;
+    Ok(rolled_back)
+}
+
+/// Whether the given version was rolled back recently enough that it shouldn't be installed again yet.
+#[cfg(unix)]
+pub(crate) async fn is_skipped(paths: &InstallPaths, version: &Version) -> Result<bool, wheel::Error> {
+    Ok(read_marker::<Failed>(&paths.failed_marker()).await?.is_some_and(|failed| failed.version == *version && failed.rolled_back_at.is_some_and(|rolled_back_at| Utc::now() - rolled_back_at < RETRY_AFTER)))
+}
+
+/// Returns the version that was rolled back if this hasn't been shown on screen yet.
+#[cfg(unix)]
+pub(crate) async fn take_rollback_report(paths: &InstallPaths) -> Result<Option<Version>, wheel::Error> {
+    match read_marker::<Failed>(&paths.failed_marker()).await? {
+        Some(Failed { version, reported: false, rolled_back_at }) => {
+            fs::write_json(paths.failed_marker(), Failed { version: version.clone(), reported: true, rolled_back_at }).await?;
+            Ok(Some(version))
+        }
+        Some(Failed { reported: true, .. }) | None => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)] use tempfile::TempDir;
+
+    #[cfg(unix)]
+    fn install(dir: &TempDir) -> InstallPaths {
+        let paths = InstallPaths::new(&PathsConfig::default(), &dir.path().join("sil"));
+        std::fs::write(&paths.bin, "old").expect("failed to write test file");
+        std::fs::write(&paths.staged_bin, "new").expect("failed to write test file");
+        paths
+    }
+
+    #[cfg(unix)]
+    fn version() -> Version {
+        Version::new(2, 0, 1)
+    }
+
+    #[cfg(unix)]
+    fn boot(n: u8) -> Option<String> {
+        Some(format!("boot {n}"))
+    }
+
+    #[cfg(unix)]
+    fn read(path: &Path) -> String {
+        std::fs::read_to_string(path).expect("failed to read test file")
+    }
+
+    #[cfg(unix)] const PUBLIC_KEY: &str = "RWTkybYy/kYn66DU2s+23ffadvfnBaLTiPn8PXnLIxmef3yvpOQeUGDl";
+    #[cfg(unix)] const SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUTkybYy/kYn6yDxFjiZ9VnehnUXUy8fmeo3CD9EX46iYoLsgl35Hd3EXGtkzSbSTvPlW+ES6Iq9Prlo0lUB29hs5KD9bqLy+Ao=
+trusted comment: sil 2.0.1
+9H8dtGOKxWkSPBIicc53+qsJw2mwVnpJZMLenJEGBcWwlconJ34dm00hM9i9lM6YBDGDcQ/9vRcGTlxCOvjfCA==
+";
+    // test vectors from minisign-verify, which don't name a version in the trusted comment
+    #[cfg(unix)] const UNVERSIONED_PUBLIC_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    #[cfg(unix)] const UNVERSIONED_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==
+";
+    #[cfg(unix)] const LEGACY_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==
+";
+
+    #[cfg(unix)]
+    #[test]
+    fn signature() {
+        verify(PUBLIC_KEY, b"test", SIGNATURE, &version()).expect("signature didn't verify");
+        verify(&format!("untrusted comment: minisign public key EB2746FE32B6C9E4\n{PUBLIC_KEY}\n"), b"test", SIGNATURE, &version()).expect("signature didn't verify with public key file");
+        assert!(verify(PUBLIC_KEY, b"tampered", SIGNATURE, &version()).is_err());
+        assert!(verify(UNVERSIONED_PUBLIC_KEY, b"test", SIGNATURE, &version()).is_err());
+        assert!(verify(UNVERSIONED_PUBLIC_KEY, b"test", LEGACY_SIGNATURE, &version()).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn signature_version() {
+        // a validly signed release of another version must not be installed
+        assert!(matches!(verify(PUBLIC_KEY, b"test", SIGNATURE, &Version::new(2, 0, 0)), Err(VerifyError::VersionMismatch { .. })));
+        assert!(matches!(verify(UNVERSIONED_PUBLIC_KEY, b"test", UNVERSIONED_SIGNATURE, &version()), Err(VerifyError::VersionMismatch { .. })));
+    }
+
+    #[test]
+    fn derived_paths() {
+        let expected = InstallPaths {
+            bin: PathBuf::from("/opt/sil/sil"),
+            staged_bin: PathBuf::from("/opt/sil/sil-reiwa"),
+        };
+        assert_eq!(InstallPaths::new(&PathsConfig::default(), Path::new("/opt/sil/sil")), expected);
+        assert_eq!(InstallPaths::new(&PathsConfig::default(), Path::new("/opt/sil/sil-reiwa")), expected);
+    }
+
+    #[test]
+    fn configured_paths() {
+        let config = PathsConfig {
+            bin: Some(PathBuf::from("/usr/local/bin/sil")),
+            staged_bin: None,
+        };
+        assert_eq!(InstallPaths::new(&config, Path::new("/tmp/sil")), InstallPaths {
+            bin: PathBuf::from("/usr/local/bin/sil"),
+            staged_bin: PathBuf::from("/usr/local/bin/sil-reiwa"),
+        });
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn staged_update_is_installed() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let paths = install(&dir);
+        assert_eq!(handoff(&paths, &paths.staged_bin, &version(), boot(1)).await.expect("handoff failed"), Handoff::Restart);
+        assert_eq!(std::fs::read_to_string(&paths.bin).expect("failed to read test file"), "new");
+        #[cfg(not(feature = "nixos"))] assert_eq!(paths.restart_target(&paths.bin), paths.staged_bin);
+        assert_eq!(handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed"), Handoff::Continue);
+        assert!(!paths.staged_bin.exists());
+        assert_eq!(std::fs::read_to_string(&paths.bin).expect("failed to read test file"), "new");
+        #[cfg(not(feature = "nixos"))] assert_eq!(paths.restart_target(&paths.bin), paths.bin);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unrelated_binary_is_left_alone() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let paths = install(&dir);
+        assert_eq!(handoff(&paths, &dir.path().join("target").join("debug").join("sil"), &version(), boot(1)).await.expect("handoff failed"), Handoff::Continue);
+        assert_eq!(std::fs::read_to_string(&paths.bin).expect("failed to read test file"), "old");
+        assert_eq!(std::fs::read_to_string(&paths.staged_bin).expect("failed to read test file"), "new");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn update_is_confirmed() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let paths = install(&dir);
+        handoff(&paths, &paths.staged_bin, &version(), boot(1)).await.expect("handoff failed");
+        assert_eq!(read(&paths.previous_bin()), "old");
+        assert!(is_pending(&paths).await.expect("failed to check pending update"));
+        assert_eq!(handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed"), Handoff::Continue);
+        confirm(&paths).await.expect("failed to confirm update");
+        assert!(!is_pending(&paths).await.expect("failed to check pending update"));
+        // a restart after the trial period doesn't roll back
+        assert_eq!(handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed"), Handoff::Continue);
+        assert_eq!(read(&paths.bin), "new");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn crashed_update_is_rolled_back() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let paths = install(&dir);
+        handoff(&paths, &paths.staged_bin, &version(), boot(1)).await.expect("handoff failed");
+        assert_eq!(handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed"), Handoff::Continue);
+        // started again without being confirmed
+        assert_eq!(handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed"), Handoff::Restart);
+        assert_eq!(read(&paths.bin), "old");
+        assert!(!is_pending(&paths).await.expect("failed to check pending update"));
+        assert!(is_skipped(&paths, &version()).await.expect("failed to read failed version"));
+        assert_eq!(take_rollback_report(&paths).await.expect("failed to read rollback report"), Some(version()));
+        assert_eq!(take_rollback_report(&paths).await.expect("failed to read rollback report"), None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn disconnected_update_is_rolled_back() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let paths = install(&dir);
+        handoff(&paths, &paths.staged_bin, &version(), boot(1)).await.expect("handoff failed");
+        handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed");
+        assert!(rollback(&paths).await.expect("rollback failed"));
+        assert_eq!(read(&paths.bin), "old");
+        // nothing left to roll back to
+        assert!(!rollback(&paths).await.expect("rollback failed"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rebooted_update_is_kept() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let paths = install(&dir);
+        handoff(&paths, &paths.staged_bin, &version(), boot(1)).await.expect("handoff failed");
+        handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed");
+        // e.g. a power cut during the trial period
+        assert_eq!(handoff(&paths, &paths.bin, &version(), boot(2)).await.expect("handoff failed"), Handoff::Continue);
+        assert_eq!(read(&paths.bin), "new");
+        assert!(is_pending(&paths).await.expect("failed to check pending update"));
+        // a crash after the reboot is still detected
+        assert_eq!(handoff(&paths, &paths.bin, &version(), boot(2)).await.expect("handoff failed"), Handoff::Restart);
+        assert_eq!(read(&paths.bin), "old");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stopped_update_is_kept() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let paths = install(&dir);
+        handoff(&paths, &paths.staged_bin, &version(), boot(1)).await.expect("handoff failed");
+        handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed");
+        stopped(&paths).await.expect("failed to record clean shutdown");
+        assert_eq!(handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed"), Handoff::Continue);
+        assert_eq!(read(&paths.bin), "new");
+        assert!(is_pending(&paths).await.expect("failed to check pending update"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn failed_update_is_retried() {
+        let dir = TempDir::new().expect("failed to create temp dir");
+        let paths = install(&dir);
+        handoff(&paths, &paths.staged_bin, &version(), boot(1)).await.expect("handoff failed");
+        handoff(&paths, &paths.bin, &version(), boot(1)).await.expect("handoff failed");
+        assert!(rollback(&paths).await.expect("rollback failed"));
+        assert!(is_skipped(&paths, &version()).await.expect("failed to read failed version"));
+        assert!(!is_skipped(&paths, &Version::new(2, 0, 2)).await.expect("failed to read failed version"));
+        // downloaded again by a version that doesn't check for rollbacks
+        std::fs::write(&paths.staged_bin, "new").expect("failed to write test file");
+        assert_eq!(handoff(&paths, &paths.staged_bin, &version(), boot(1)).await.expect("handoff failed"), Handoff::Refused);
+        assert_eq!(read(&paths.bin), "old");
+        assert!(!paths.staged_bin.exists());
+        fs::write_json(paths.failed_marker(), Failed { version: version(), reported: true, rolled_back_at: Some(Utc::now() - RETRY_AFTER) }).await.expect("failed to write test file");
+        assert!(!is_skipped(&paths, &version()).await.expect("failed to read failed version"));
+    }
+}
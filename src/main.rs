@@ -1,11 +1,13 @@
 use {
     std::{
-        collections::HashMap,
+        borrow::Cow,
         env,
         io,
+        mem,
         num::NonZero,
         process,
         rc::Rc,
+        sync::Arc,
         time::{
             Duration,
             Instant,
@@ -15,35 +17,26 @@ use {
         TimeDelta,
         prelude::*,
     },
-    chrono_tz::Tz,
-    fontdue::{
-        Font,
-        FontSettings,
-        layout::{
-            GlyphRasterConfig,
-            VerticalAlign,
-        },
+    fontdue::layout::{
+        HorizontalAlign,
+        VerticalAlign,
     },
     if_chain::if_chain,
-    itertools::Itertools as _,
     rand::prelude::*,
-    softbuffer::SoftBufferError,
     tiny_skia::*,
     tokio::{
         sync::{
             mpsc,
             oneshot,
+            watch,
         },
         process::Command,
         time::sleep,
     },
     tokio_tungstenite::tungstenite,
-    wheel::{
-        fs,
-        traits::{
-            AsyncCommandOutputExt as _,
-            LocalResultExt as _,
-        },
+    wheel::traits::{
+        AsyncCommandOutputExt as _,
+        IoResultExt as _,
     },
     winit::{
         dpi::{
@@ -51,7 +44,9 @@ use {
             PhysicalSize,
         },
         event::{
+            ElementState,
             Event,
+            KeyEvent,
             StartCause,
             WindowEvent,
         },
@@ -59,44 +54,73 @@ use {
             ControlFlow,
             EventLoop,
         },
+        keyboard::{
+            Key,
+            NamedKey,
+        },
         window::{
             Fullscreen,
             Window,
         },
     },
-    crate::state::State,
+    crate::{
+        clock::{
+            Clock,
+            FakeTime,
+        },
+        config::{
+            Config,
+            DisplayConfig,
+            Theme,
+        },
+        install::InstallPaths,
+        font::{
+            Face,
+            Fonts,
+        },
+        glyph_cache::GlyphCache,
+        image::Image,
+        markup::Text,
+        output::{
+            Mirror,
+            Output,
+            PixelMap,
+            Rotation,
+            SafeArea,
+        },
+        state::{
+            State,
+            Status,
+        },
+    },
 };
 #[cfg(unix)] use {
-    std::{
-        os::unix::process::CommandExt as _,
-        path::Path,
+    std::os::unix::process::CommandExt as _,
+    tokio::signal::unix::{
+        SignalKind,
+        signal,
     },
-    wheel::traits::IoResultExt as _,
+    crate::install::Handoff,
 };
-#[cfg(all(not(feature = "nixos"), unix))] use std::sync::Arc;
 
+mod check_config;
+mod clock;
 mod config;
+mod font;
+mod glyph_cache;
+mod image;
+mod install;
+mod markup;
+mod output;
+mod render;
+mod scheduler;
 mod state;
 
-#[cfg(unix)] const BIN_PATH: &str = "/home/fenhl/bin/sil";
-#[cfg(unix)] const REIWA_BIN_PATH: &str = "/home/fenhl/bin/sil-reiwa";
-
-#[cfg(target_os = "linux")] const DEJAVU_PATH: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
-#[cfg(target_os = "macos")] const DEJAVU_PATH: &str = "/Users/fenhl/Library/Fonts/DejaVuSans.ttf";
-#[cfg(target_os = "windows")] const DEJAVU_PATH: &str = "\\Windows\\Fonts\\DejaVuSans.ttf";
-
-const NIXOS_DEJAVU_PATH: &str = "/run/current-system/sw/share/X11/fonts/DejaVuSans.ttf";
-
 trait ControlFlowExt {
-    fn redraw_immediately(&mut self);
     fn redraw_at(&mut self, new_time: Instant);
 }
 
 impl ControlFlowExt for ControlFlow {
-    fn redraw_immediately(&mut self) {
-        *self = Self::Poll;
-    }
-
     fn redraw_at(&mut self, new_time: Instant) {
         match self {
             ControlFlow::Wait => *self = Self::WaitUntil(new_time),
@@ -106,42 +130,103 @@ impl ControlFlowExt for ControlFlow {
     }
 }
 
-#[derive(Debug, thiserror::Error)]
-enum DrawError {
-    #[error(transparent)] Text(#[from] text::Error),
-    #[error(transparent)] TimeFromLocal(#[from] wheel::traits::TimeFromLocalError<DateTime<Tz>>),
+/// Returns the 16-bit pattern displayed by `State::BinaryTime` at the given time of day, and how long until it next changes.
+fn binary_time(time: NaiveTime) -> (u16, Duration) {
+    const NANOS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+    let nanos_since_midnight = u64::from(time.num_seconds_from_midnight()) * 1_000_000_000 + u64::from(time.nanosecond() % 1_000_000_000); // nanosecond can exceed 1 billion during leap seconds
+    let bit_pattern = nanos_since_midnight * 65536 / NANOS_PER_DAY;
+    let next_change = ((bit_pattern + 1) * NANOS_PER_DAY).div_ceil(65536);
+    (bit_pattern as u16, Duration::from_nanos(next_change - nanos_since_midnight))
 }
 
 struct DrawCache {
     dark: bool,
     state: State,
     canvas: Pixmap,
+    screen_size: (u32, u32),
+    output: Output,
+    pixel_map: PixelMap,
+    clock: Clock,
     redraw_at: ControlFlow,
-    logo: Option<Pixmap>,
-    text_layout: fontdue::layout::Layout,
-    dejavu_sans: Font,
-    glyph_cache: HashMap<(GlyphRasterConfig, [u8; 4]), Pixmap>, // ColorU8 does not implement Eq or Hash
+    renderer: markup::Renderer,
+    debug_overlay: bool,
+    /// Set if the config file was edited and can't be reloaded.
+    config_error: Option<config::Error>,
+    /// Config fields which were edited but only take effect after a restart.
+    restart_required: Vec<&'static str>,
+    /// A message shown in the warning banner until the given time, e.g. after an update was rolled back.
+    notice: Option<(Instant, String)>,
+    /// The display's id and roles from the config.
+    display: String,
+    status: Status,
+    /// How long drawing and presenting the previous frame took.
+    frame_time: Option<Duration>,
 }
 
 impl DrawCache {
-    fn draw(&mut self) -> Result<(), DrawError> {
+    async fn new(config: &Config, dark: bool, output: Output, state: State) -> Result<Self, Error> {
+        let renderer = markup::Renderer::new(Fonts::load(&config.fonts).await?, GlyphCache::new(config.glyph_cache_budget_mib * 1024 * 1024));
+        Self::with_renderer(renderer, dark, output, state).ok_or(Error::Pixmap)
+    }
+
+    /// Returns `None` if the initial canvas could not be created.
+    fn with_renderer(mut renderer: markup::Renderer, dark: bool, output: Output, state: State) -> Option<Self> {
+        renderer.scale = output.render_scale();
+        Some(Self {
+            canvas: Pixmap::new(100, 100)?,
+            screen_size: (100, 100),
+            pixel_map: PixelMap::default(),
+            clock: Clock::System,
+            redraw_at: ControlFlow::Poll,
+            debug_overlay: false,
+            config_error: None,
+            restart_required: Vec::default(),
+            notice: None,
+            display: String::default(),
+            status: Status::default(),
+            frame_time: None,
+            dark, state, output, renderer,
+        })
+    }
+
+    fn foreground(&self) -> Color {
+        if self.dark { Color::WHITE } else { Color::BLACK }
+    }
+
+    /// The `0RGB` color for the area outside the safe area.
+    fn background(&self) -> u32 {
+        if self.dark { 0x000000 } else { 0xffffff }
+    }
+
+    fn accent(&self) -> Color {
+        if self.dark { Color::from_rgba8(0xff, 0xcc, 0x00, 0xff) } else { Color::from_rgba8(0x99, 0x66, 0x00, 0xff) }
+    }
+
+    /// Schedules a redraw for when the clock has advanced by the given duration.
+    fn redraw_after(&mut self, duration: Duration) {
+        if let Some(deadline) = self.clock.deadline(duration) {
+            self.redraw_at.redraw_at(deadline);
+        }
+    }
+
+    fn draw(&mut self) {
         self.redraw_at = ControlFlow::Wait;
+        self.renderer.glyph_cache.begin_frame();
         let width = self.canvas.width() as f32;
         let height = self.canvas.height() as f32;
-        let now_monotonic = Instant::now();
-        let now_utc = Utc::now();
+        let now_utc = self.clock.now();
         #[cfg(debug_assertions)] {
-            println!("{} redrawing for {:?}", now_utc.format("%Y-%m-%d %H:%M:%S"), self.state);
+            let glyph_cache = self.renderer.glyph_cache.stats();
+            println!("{} redrawing for {:?} (glyph cache: {} glyphs, {} KiB, {} hits, {} misses, {} evictions)", now_utc.format("%Y-%m-%d %H:%M:%S"), self.state, glyph_cache.entries, glyph_cache.bytes / 1024, glyph_cache.hits, glyph_cache.misses, glyph_cache.evictions);
         }
         self.canvas.fill(if self.dark { Color::BLACK } else { Color::WHITE });
         match self.state {
             State::BinaryTime(tz) => {
-                self.redraw_at.redraw_immediately(); //TODO redraw on next image change
                 let width = self.canvas.width();
                 let height = self.canvas.height();
-                let now = now_utc.with_timezone(&tz);
-                let bit_pattern = (now.time() - NaiveTime::from_hms_opt(0, 0, 0).expect("invalid hardcoded daytime")).to_std().expect("nonnegative time of day").as_secs_f32() * (65536.0 / 86_400.0);
-                let bit_pattern = bit_pattern as u16;
+                let (bit_pattern, until_next_change) = binary_time(now_utc.with_timezone(&tz).time());
+                self.redraw_after(until_next_change);
                 for (i, p) in self.canvas.pixels_mut().iter_mut().enumerate() {
                     let x = i as u32 % width;
                     let y = i as u32 / width;
@@ -154,118 +239,273 @@ impl DrawCache {
                     };
                 }
             }
+            State::Calibration => {
+                let mut paint = Paint {
+                    anti_alias: false,
+                    ..Paint::default()
+                };
+                paint.set_color_rgba8(0x80, 0x80, 0x80, 0xff);
+                for i in 1..10 {
+                    let x = (width * i as f32 / 10.0).floor();
+                    let y = (height * i as f32 / 10.0).floor();
+                    self.canvas.fill_rect(Rect::from_xywh(x, 0.0, 1.0, height).expect("invalid grid line"), &paint, Transform::identity(), None);
+                    self.canvas.fill_rect(Rect::from_xywh(0.0, y, width, 1.0).expect("invalid grid line"), &paint, Transform::identity(), None);
+                }
+                // the edges of the canvas are the edges of the safe area, so these should be fully visible but as close to the edges of the screen as possible
+                paint.set_color_rgba8(0xff, 0x00, 0x00, 0xff);
+                let thickness = 4.0_f32.min(width / 2.0).min(height / 2.0);
+                for rect in [
+                    Rect::from_xywh(0.0, 0.0, width, thickness),
+                    Rect::from_xywh(0.0, height - thickness, width, thickness),
+                    Rect::from_xywh(0.0, 0.0, thickness, height),
+                    Rect::from_xywh(width - thickness, 0.0, thickness, height),
+                ] {
+                    self.canvas.fill_rect(rect.expect("invalid edge marker"), &paint, Transform::identity(), None);
+                }
+                let arrow = (width.min(height) / 10.0).max(1.0);
+                for [tip, left, right] in [
+                    [(width / 2.0, 0.0), (width / 2.0 - arrow, arrow), (width / 2.0 + arrow, arrow)],
+                    [(width / 2.0, height), (width / 2.0 - arrow, height - arrow), (width / 2.0 + arrow, height - arrow)],
+                    [(0.0, height / 2.0), (arrow, height / 2.0 - arrow), (arrow, height / 2.0 + arrow)],
+                    [(width, height / 2.0), (width - arrow, height / 2.0 - arrow), (width - arrow, height / 2.0 + arrow)],
+                ] {
+                    let mut path = PathBuilder::new();
+                    path.move_to(tip.0, tip.1);
+                    path.line_to(left.0, left.1);
+                    path.line_to(right.0, right.1);
+                    path.close();
+                    if let Some(path) = path.finish() {
+                        self.canvas.fill_path(&path, &paint, FillRule::Winding, Transform::identity(), None);
+                    }
+                }
+                let (screen_width, screen_height) = self.output.logical_size(self.screen_size.0, self.screen_size.1);
+                markup::Builder::new(&Text::plain(format!("safe area: {}\n{width}x{height} of {screen_width}x{screen_height}\nrotation: {}, mirror: {:?}, render scale: {}", self.output.safe_area, self.output.rotation, self.output.mirror, self.output.render_scale())))
+                    .color(self.foreground())
+                    .size(48.0)
+                    .draw(&mut self.renderer, self.canvas.as_mut());
+            }
             State::CloseWindows(tz) => {
                 let nanos_until_next_second = 1_000_000_000 - now_utc.timestamp_subsec_nanos() % 1_000_000_000;
-                self.redraw_at.redraw_at(now_monotonic + Duration::from_nanos(nanos_until_next_second.into()));
-                text::Builder::new(&self.dejavu_sans, &format!("Es ist {} Uhr.\nBitte alle Fenster schließen.", now_utc.with_timezone(&tz).format("%H:%M:%S")))
-                    .color(if self.dark { Color::WHITE } else { Color::BLACK })
+                self.redraw_after(Duration::from_nanos(nanos_until_next_second.into()));
+                markup::Builder::new(&Text::plain(format!("Es ist {} Uhr.\nBitte alle Fenster schließen.", now_utc.with_timezone(&tz).format("%H:%M:%S"))))
+                    .color(self.foreground())
                     .size(100.0)
-                    .build(&mut self.text_layout, [width, height])?
-                    .draw(self.canvas.as_mut(), &mut self.glyph_cache)?;
+                    .draw(&mut self.renderer, self.canvas.as_mut());
             }
             State::Error(ref e) => {
                 self.canvas.fill(Color::from_rgba8(0xff, 0x00, 0x00, 0xff));
-                text::Builder::new(&self.dejavu_sans, &format!("{e}\n\n{e:?}"))
+                let text = if let Some(display) = e.server_display() {
+                    let mut text = Text::parse(display);
+                    text.push_plain(format!("\n\n{e:?}"));
+                    text
+                } else {
+                    Text::plain(format!("{e}\n\n{e:?}"))
+                };
+                markup::Builder::new(&text)
                     .color(Color::WHITE)
+                    .accent(Color::from_rgba8(0xff, 0xcc, 0x00, 0xff))
                     .size(100.0)
-                    .build(&mut self.text_layout, [width, height])?
-                    .draw(self.canvas.as_mut(), &mut self.glyph_cache)?;
+                    .draw(&mut self.renderer, self.canvas.as_mut());
             }
             State::HexagesimalTime(tz) => {
                 let nanos_until_next_second = 1_000_000_000 - now_utc.timestamp_subsec_nanos() % 1_000_000_000;
-                self.redraw_at.redraw_at(now_monotonic + Duration::from_nanos(nanos_until_next_second.into()));
-                text::Builder::new(&self.dejavu_sans, &now_utc.with_timezone(&tz).format("%d.%m.%Y %H:%M:%S").to_string())
-                    .color(if self.dark { Color::WHITE } else { Color::BLACK })
+                self.redraw_after(Duration::from_nanos(nanos_until_next_second.into()));
+                markup::Builder::new(&Text::plain(now_utc.with_timezone(&tz).format("%d.%m.%Y %H:%M:%S").to_string()))
+                    .color(self.foreground())
                     .size(100.0)
-                    .build(&mut self.text_layout, [width, height])?
-                    .draw(self.canvas.as_mut(), &mut self.glyph_cache)?;
+                    .draw(&mut self.renderer, self.canvas.as_mut());
             }
-            State::Logo { msg } => {
-                if let Some(logo) = &self.logo {
-                    //TODO resize Gefolge logo on small resolutions
-                    self.canvas.draw_pixmap(0, 0, logo.as_ref(), &PixmapPaint::default(), Transform::from_translate((width - logo.width() as f32) / 2.0, (height - logo.height() as f32) / 2.0), None);
+            State::Logo { ref msg } => {
+                if let Some(logo) = self.renderer.icons.get_mut("logo") {
+                    logo.draw_fit(&mut self.canvas.as_mut(), width / 8.0, height / 8.0, width * 0.75, height * 0.75);
                 }
-                text::Builder::new(&self.dejavu_sans, &format!("{width}x{height}"))
-                    .color(if self.dark { Color::WHITE } else { Color::BLACK })
+                markup::Builder::new(&Text::plain(format!("{width}x{height}")))
+                    .color(self.foreground())
                     .size(24.0)
                     .valign(VerticalAlign::Top)
-                    .build(&mut self.text_layout, [width, height])?
-                    .draw(self.canvas.as_mut(), &mut self.glyph_cache)?;
-                text::Builder::new(&self.dejavu_sans, msg)
-                    .color(if self.dark { Color::WHITE } else { Color::BLACK })
+                    .draw(&mut self.renderer, self.canvas.as_mut());
+                markup::Builder::new(&Text::parse(msg))
+                    .color(self.foreground())
+                    .accent(self.accent())
                     .size(24.0)
                     .valign(VerticalAlign::Bottom)
-                    .build(&mut self.text_layout, [width, height])?
-                    .draw(self.canvas.as_mut(), &mut self.glyph_cache)?;
+                    .draw(&mut self.renderer, self.canvas.as_mut());
             }
             State::NewYear(tz) => {
                 let now = now_utc.with_timezone(&tz);
-                if now.month() > 6 {
+                if let Some(mut delta) = state::new_year_countdown(now) {
                     let nanos_until_next_second = 1_000_000_000 - now_utc.timestamp_subsec_nanos() % 1_000_000_000;
-                    self.redraw_at.redraw_at(now_monotonic + Duration::from_nanos(nanos_until_next_second.into()));
-                    let mut delta = now.timezone().with_ymd_and_hms(now.year() + 1, 1, 1, 0, 0, 0).single_ok()? - now;
+                    self.redraw_after(Duration::from_nanos(nanos_until_next_second.into()));
                     if delta < TimeDelta::minutes(1) {
-                        text::Builder::new(&self.dejavu_sans, &delta.num_seconds().to_string())
-                            .color(if self.dark { Color::WHITE } else { Color::BLACK })
+                        markup::Builder::new(&Text::plain(delta.num_seconds().to_string()))
+                            .face(Face::Display)
+                            .color(self.foreground())
                             .size(400.0)
-                            .build(&mut self.text_layout, [width, height])?
-                            .draw(self.canvas.as_mut(), &mut self.glyph_cache)?;
+                            .draw(&mut self.renderer, self.canvas.as_mut());
                     } else if delta < TimeDelta::hours(1) {
                         let mins = delta.num_minutes();
                         delta = delta - TimeDelta::minutes(mins);
-                        text::Builder::new(&self.dejavu_sans, &format!("{mins}:{:02}", delta.num_seconds()))
-                            .color(if self.dark { Color::WHITE } else { Color::BLACK })
+                        markup::Builder::new(&Text::plain(format!("{mins}:{:02}", delta.num_seconds())))
+                            .face(Face::Display)
+                            .color(self.foreground())
                             .size(200.0)
-                            .build(&mut self.text_layout, [width, height])?
-                            .draw(self.canvas.as_mut(), &mut self.glyph_cache)?;
+                            .draw(&mut self.renderer, self.canvas.as_mut());
                     } else {
                         let hours = delta.num_hours();
                         delta = delta - TimeDelta::hours(hours);
                         let mins = delta.num_minutes();
                         delta = delta - TimeDelta::minutes(mins);
-                        text::Builder::new(&self.dejavu_sans, &format!("{hours}:{mins:02}:{:02}", delta.num_seconds()))
-                            .color(if self.dark { Color::WHITE } else { Color::BLACK })
+                        markup::Builder::new(&Text::plain(format!("{hours}:{mins:02}:{:02}", delta.num_seconds())))
+                            .face(Face::Display)
+                            .color(self.foreground())
                             .size(200.0)
-                            .build(&mut self.text_layout, [width, height])?
-                            .draw(self.canvas.as_mut(), &mut self.glyph_cache)?;
+                            .draw(&mut self.renderer, self.canvas.as_mut());
                     }
                 } else {
                     //TODO shrink text on small resolutions to avoid line wrap
-                    text::Builder::new(&self.dejavu_sans, &now.year().to_string())
-                        .color(if self.dark { Color::WHITE } else { Color::BLACK })
+                    markup::Builder::new(&Text::plain(now.year().to_string()))
+                        .face(Face::Display)
+                        .color(self.foreground())
                         .size(400.0)
-                        .build(&mut self.text_layout, [width, height])?
-                        .draw(self.canvas.as_mut(), &mut self.glyph_cache)?;
+                        .draw(&mut self.renderer, self.canvas.as_mut());
                 }
             }
         }
-        Ok(())
+        if let Some(ref e) = self.config_error {
+            let msg = format!("invalid config file, changes not applied: {e}");
+            self.draw_warning(&msg);
+        } else if let Some((until, msg)) = self.notice.take().filter(|(until, _)| *until > Instant::now()) {
+            self.redraw_at.redraw_at(until);
+            self.draw_warning(&msg);
+            self.notice = Some((until, msg));
+        } else if !self.restart_required.is_empty() {
+            let msg = format!("restart sil to apply changes to {}", self.restart_required.join(", "));
+            self.draw_warning(&msg);
+        }
+        if self.debug_overlay {
+            self.draw_debug_overlay(now_utc);
+        }
+    }
+
+    /// Draws a banner along the bottom edge of the canvas.
+    fn draw_warning(&mut self, msg: &str) {
+        const SIZE: f32 = 20.0;
+
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(0xff, 0x00, 0x00, 0xc0);
+        let height = SIZE * 1.25 * self.renderer.scale;
+        if let Some(rect) = Rect::from_xywh(0.0, self.canvas.height() as f32 - height, self.canvas.width() as f32, height) {
+            self.canvas.fill_rect(rect, &paint, Transform::identity(), None);
+        }
+        markup::Builder::new(&Text::plain(msg))
+            .color(Color::WHITE)
+            .size(SIZE)
+            .valign(VerticalAlign::Bottom)
+            .draw(&mut self.renderer, self.canvas.as_mut());
+    }
+
+    fn draw_debug_overlay(&mut self, now_utc: DateTime<Utc>) {
+        const SIZE: f32 = 20.0;
+
+        let now_monotonic = Instant::now();
+        let nanos_until_next_second = 1_000_000_000 - now_utc.timestamp_subsec_nanos() % 1_000_000_000;
+        self.redraw_after(Duration::from_nanos(nanos_until_next_second.into()));
+        let glyph_cache = self.renderer.glyph_cache.stats();
+        let lines = [
+            concat!("sil ", env!("CARGO_PKG_VERSION")).to_owned(),
+            format!("display: {}", self.display),
+            if let Some((mode, priority)) = self.status.mode {
+                format!("mode: {mode:?} ({priority:?})")
+            } else {
+                "mode: none".to_owned()
+            },
+            if let Some(next_rotation) = self.status.next_rotation {
+                format!("next rotation in {}s", next_rotation.saturating_duration_since(now_monotonic).as_secs())
+            } else {
+                "next rotation: not scheduled".to_owned()
+            },
+            format!("connection: {:?}", self.status.connection),
+            if let Some((received_at, ref msg)) = self.status.last_message {
+                format!("last message: {msg} ({}s ago)", now_monotonic.saturating_duration_since(received_at).as_secs())
+            } else {
+                "last message: none".to_owned()
+            },
+            if let Some(frame_time) = self.frame_time {
+                format!("frame time: {:.1}ms", frame_time.as_secs_f64() * 1000.0)
+            } else {
+                "frame time: unknown".to_owned()
+            },
+            format!("glyph cache: {} glyphs, {} KiB, {} hits, {} misses, {} evictions", glyph_cache.entries, glyph_cache.bytes / 1024, glyph_cache.hits, glyph_cache.misses, glyph_cache.evictions),
+        ];
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(0x00, 0x00, 0x00, 0xc0);
+        if let Some(rect) = Rect::from_xywh(0.0, 0.0, self.canvas.width() as f32, lines.len() as f32 * SIZE * 1.25 * self.renderer.scale) {
+            self.canvas.fill_rect(rect, &paint, Transform::identity(), None);
+        }
+        markup::Builder::new(&Text::plain(lines.join("\n")))
+            .color(Color::WHITE)
+            .size(SIZE)
+            .halign(HorizontalAlign::Left)
+            .valign(VerticalAlign::Top)
+            .draw(&mut self.renderer, self.canvas.as_mut());
+    }
+
+    /// Rasterizes the digits used by the current state's countdown or clock ahead of time, so the first seconds of a countdown don't stutter.
+    fn prewarm(&mut self) {
+        let (face, sizes): (_, &[f32]) = match self.state {
+            State::CloseWindows(_) => (Face::Bold, &[100.0]),
+            State::HexagesimalTime(_) => (Face::Regular, &[100.0]),
+            State::NewYear(_) => (Face::Display, &[200.0, 400.0]),
+            State::BinaryTime(_) | State::Calibration | State::Error(_) | State::Logo { .. } => return,
+        };
+        // the canvas is cleared at the start of the next draw, so it can be used as scratch space here
+        let digits = Text::plain("0123456789:");
+        for &size in sizes {
+            markup::Builder::new(&digits)
+                .face(face)
+                .color(self.foreground())
+                .size(size)
+                .draw(&mut self.renderer, self.canvas.as_mut());
+        }
     }
 }
 
 #[derive(Debug, thiserror::Error)]
 enum Error {
     #[error(transparent)] Config(#[from] config::Error),
+    /// Connecting to or authenticating with the server failed. Unlike other errors, this rolls back an update that's still in its trial period.
+    #[error(transparent)] Connect(Box<Error>),
     #[error(transparent)] EventLoop(#[from] winit::error::EventLoopError),
     #[error(transparent)] EventLoopClosed(#[from] winit::event_loop::EventLoopClosed<UserEvent>),
+    #[error(transparent)] Font(#[from] font::Error),
     #[error(transparent)] Io(#[from] io::Error),
-    #[error(transparent)] Png(#[from] png::DecodingError),
+    #[error(transparent)] Image(#[from] image::Error),
+    #[error(transparent)] PngEncode(#[from] png::EncodingError),
     #[error(transparent)] Read(#[from] async_proto::ReadError),
     #[error(transparent)] Reqwest(#[from] reqwest::Error),
     #[error(transparent)] SendState(#[from] mpsc::error::SendError<State>),
     #[error(transparent)] Task(#[from] tokio::task::JoinError),
     #[error(transparent)] Tungstenite(#[from] tungstenite::Error),
+    #[error(transparent)] Url(#[from] url::ParseError),
+    #[cfg(all(not(feature = "nixos"), unix))]
+    #[error("failed to verify the signature of the update: {0}")]
+    UpdateSignature(#[from] install::VerifyError),
     #[error(transparent)] Wheel(#[from] wheel::Error),
     #[error(transparent)] Write(#[from] async_proto::WriteError),
     #[error("WebSocket stream ended")]
     EndOfStream,
-    #[error("{0}")]
-    Font(&'static str),
     #[cfg(windows)]
     #[error("user folder not found")]
     MissingHomeDir,
+    /// A placeholder error displayed by `sil render error`.
+    #[error("{0}")]
+    Mock(String),
+    #[cfg(all(not(feature = "nixos"), unix))]
+    #[error("update URL is configured without a public key to verify the download")]
+    MissingUpdatePublicKey,
     #[error("failed to create canvas")]
     Pixmap,
+
     #[error("{display}")]
     Server {
         debug: String,
@@ -273,83 +513,199 @@ enum Error {
     },
 }
 
-fn pixmap_to_softbuf<D: raw_window_handle::HasDisplayHandle, W: raw_window_handle::HasWindowHandle>(pixmap: PixmapRef<'_>, mut buffer: softbuffer::Buffer<'_, D, W>) -> Result<(), SoftBufferError> {
-    for (src, target) in pixmap.pixels().iter().zip_eq(&mut *buffer) {
-        *target = (u32::from(src.red()) << 16) | (u32::from(src.green()) << 8) | u32::from(src.blue());
+impl Error {
+    /// The message of an error sent by the server, which may contain markup.
+    fn server_display(&self) -> Option<&str> {
+        match self {
+            Self::Server { display, .. } => Some(display),
+            Self::Connect(e) => e.server_display(),
+            _ => None,
+        }
     }
-    buffer.present() //TODO calculate changed rects and use present_with_damage? (need to keep track of previous coords and sizes of all items)
 }
 
 #[derive(Debug)]
 enum UserEvent {
     State(State),
-    Logo(Pixmap),
+    Clock(Clock),
+    Icon(String, Image),
+    Status(Box<Status>),
+    /// The config file was changed. Also lists the changed fields that only take effect after a restart.
+    Config(Arc<Config>, Vec<&'static str>),
+    /// The config file was changed but the new version couldn't be loaded.
+    ConfigError(config::Error),
     UpdateDone,
 }
 
+#[derive(clap::Subcommand)]
+enum Subcommand {
+    /// Find problems in the config file without starting the display
+    CheckConfig(check_config::Args),
+    /// Draw a state into a PNG file without opening a window
+    Render(render::Args),
+}
+
 #[derive(clap::Parser)]
 #[clap(version)]
 struct Args {
-    /// Use a light theme with mostly white backgrounds and black text
+    #[clap(subcommand)]
+    subcommand: Option<Subcommand>,
+    /// Use a light theme with mostly white backgrounds and black text. Overrides the config file.
     #[clap(short, long)]
     light: bool,
+    /// Use a dark theme with black backgrounds and white text. Overrides the config file.
+    #[clap(long, conflicts_with("light"))]
+    dark: bool,
     /// Pretend that there's currently an ongoing event for debugging purposes
     #[clap(short, long)]
     mock_event: bool,
-    /// Always display a hardcoded state for debugging purposes
-    #[clap(long, conflicts_with("mock_event"))]
-    mock_state: bool,
+    /// Always display the given state for debugging purposes, e.g. `--mock-state new-year --tz Pacific/Auckland` (the timezone defaults to Europe/Berlin). Must be the last option; see `--mock-state help` for the available states. Without a state, shows binary time in UTC.
+    #[clap(long, conflicts_with("mock_event"), value_name = "STATE", num_args = 0.., allow_hyphen_values = true)]
+    mock_state: Option<Vec<String>>,
+    /// Display a test pattern for adjusting the safe area instead of connecting to gefolge.org
+    #[clap(long, conflicts_with_all(["mock_event", "mock_state"]))]
+    calibrate: bool,
+    /// Keep content away from the screen edges, e.g. "2%" or "20 40" (like CSS margin: top, right, bottom, left). Overrides the config file.
+    #[clap(long)]
+    safe_area: Option<SafeArea>,
+    /// Rotate the display clockwise by 0, 90, 180, or 270 degrees, e.g. 180 for a ceiling-mounted projector. Overrides the config file.
+    #[clap(long)]
+    rotate: Option<Rotation>,
+    /// Mirror the display, e.g. horizontally for a rear-projection screen. Overrides the config file.
+    #[clap(long, value_enum)]
+    mirror: Option<Mirror>,
+    /// Render at a fraction of the native resolution (between 0.1 and 1) to improve frame rate on slow devices. Overrides the config file.
+    #[clap(long)]
+    render_scale: Option<f32>,
     #[clap(short = 'U', long)]
     no_self_update: bool,
+    /// Pretend that sil was started at this time, e.g. 2024-12-31T23:55:00 (in the event's timezone) or 2024-12-31T23:55:00+01:00
+    #[clap(long)]
+    fake_time: Option<FakeTime>,
+    /// Make time pass this many times as fast as normal, e.g. 60 to rehearse an hour in a minute. At most 100000.
+    #[clap(long, default_value_t = 1.0, value_parser = clock::parse_scale)]
+    time_scale: f64,
+    /// Show diagnostic information on top of the current state. Can also be toggled with F3.
+    #[clap(long)]
+    debug_overlay: bool,
+    /// Seed for random decisions such as the order of modes, to reproduce a previous run. A random seed is chosen and printed if omitted.
+    #[clap(long)]
+    seed: Option<u64>,
     #[clap(short, long)]
     windowed: bool,
-    /// Connect to the specified WebSocket server instead of gefolge.org
-    #[clap(long, default_value = "wss://gefolge.org/api/v2/websocket")]
-    ws_url: String,
+    /// Connect to the specified WebSocket server instead of gefolge.org. Overrides the config file.
+    #[clap(long)]
+    ws_url: Option<String>,
 }
 
-#[wheel::main]
-async fn main(Args { light, mock_event, mock_state, no_self_update, windowed, ws_url }: Args) -> Result<i32, Error> {
-    #[cfg(unix)] let current_exe = env::current_exe().at_unknown()?; // determine at the start of the program, before anything can delete it
-    #[cfg(unix)] {
-        if current_exe == Path::new(REIWA_BIN_PATH) {
-            fs::copy(REIWA_BIN_PATH, BIN_PATH).await?;
-            return Err(process::Command::new(BIN_PATH).exec().into())
-        } else if current_exe == Path::new(BIN_PATH) && Path::new(REIWA_BIN_PATH).exists() {
-            fs::remove_file(REIWA_BIN_PATH).await?;
-        }
+/// Applies the command-line overrides to the display config.
+fn output(config: &DisplayConfig, safe_area: Option<SafeArea>, rotate: Option<Rotation>, mirror: Option<Mirror>, render_scale: Option<f32>) -> Output {
+    Output {
+        safe_area: safe_area.unwrap_or(config.safe_area),
+        rotation: rotate.unwrap_or(config.rotate),
+        mirror: mirror.unwrap_or(config.mirror),
+        burn_in: config.burn_in.clone(),
+        render_scale: render_scale.unwrap_or(config.render_scale),
     }
+}
+
+#[wheel::main]
+async fn main(Args { subcommand, light, dark, mock_event, mock_state, calibrate, safe_area, rotate, mirror, render_scale, no_self_update, fake_time, time_scale, debug_overlay, seed, windowed, ws_url }: Args) -> Result<i32, Error> {
     let http_client = reqwest::Client::builder()
         .user_agent(concat!("sil/", env!("CARGO_PKG_VERSION"), " (https://github.com/dasgefolge/sil)"))
         .use_rustls_tls()
         .https_only(true)
         .build()?;
-    let mut cache = DrawCache {
-        dark: !light,
-        state: State::Logo {
-            msg: "loading the loader",
-        },
-        canvas: Pixmap::new(100, 100).ok_or(Error::Pixmap)?,
-        redraw_at: ControlFlow::Poll,
-        logo: None,
-        text_layout: fontdue::layout::Layout::new(fontdue::layout::CoordinateSystem::PositiveYDown),
-        dejavu_sans: Font::from_bytes(if fs::exists(NIXOS_DEJAVU_PATH).await? {
-            fs::read(NIXOS_DEJAVU_PATH).await?
-        } else {
-            fs::read(DEJAVU_PATH).await?
-        }, FontSettings {
-            scale: 100.0,
-            ..FontSettings::default()
-        }).map_err(Error::Font)?,
-        glyph_cache: HashMap::default(),
+    let mock_state = mock_state.map(render::StateSpec::parse_args).transpose().unwrap_or_else(|e| e.exit());
+    match subcommand {
+        // loads the config itself to report errors in detail
+        Some(Subcommand::CheckConfig(args)) => return check_config::check_config(args).await,
+        Some(Subcommand::Render(args)) => {
+            render::render(&http_client, &Config::load().await?, args).await?;
+            return Ok(0)
+        }
+        None => {}
+    }
+    let current_exe = env::current_exe().at_unknown()?; // determine at the start of the program, before anything can delete it
+    let config = Config::load().await;
+    let install_paths = match config {
+        Ok(ref config) => InstallPaths::new(&config.paths, &current_exe),
+        // a staged update may reject the config file of the version it replaces, but it still has to install itself so it isn't downloaded again
+        Err(_) => InstallPaths::new(&Config::load_paths().await, &current_exe),
+    };
+    #[cfg(unix)] {
+        match install::handoff(&install_paths, &current_exe, &install::current_version(), install::boot_id().await).await? {
+            Handoff::Restart => return Err(process::Command::new(&install_paths.bin).exec().into()),
+            // every version of sil has this flag, including those which don't check for rollbacks
+            Handoff::Refused => return Err(process::Command::new(&install_paths.bin).arg("--no-self-update").exec().into()),
+            Handoff::Continue => {}
+        }
+    }
+    let (config, config_error) = match config {
+        Ok(config) => (config, None),
+        Err(e) => {
+            // this happens before a window is opened, so make sure the reason is visible in the service logs
+            eprintln!("failed to load the config file, run `sil check-config` for details: {e}");
+            (Config::default(), Some(e))
+        }
     };
+    let config_valid = config_error.is_none();
+    let mut cache = DrawCache::new(&config, config.theme.with_flags(light, dark) == Theme::Dark, output(&config.display, safe_area, rotate, mirror, render_scale), State::Logo { msg: Cow::Borrowed("loading the loader") }).await?;
+    if let Some(e) = config_error {
+        // exiting would leave the display blank while the service is restarted over and over, so show the error until the file is fixed
+        cache.state = State::Error(Arc::new(e.into()));
+    }
+    cache.debug_overlay = debug_overlay;
+    cache.display = config.display.description();
+    cache.clock = Clock::new(fake_time, time_scale);
+    #[cfg(unix)] {
+        if let Some(version) = install::take_rollback_report(&install_paths).await? {
+            let msg = format!("update to sil {version} failed to start, rolled back to {}", env!("CARGO_PKG_VERSION"));
+            eprintln!("{msg}");
+            cache.notice = Some((Instant::now() + Duration::from_secs(10 * 60), msg));
+        }
+    }
     let event_loop = EventLoop::with_user_event().build()?;
+    let (config_tx, mut config_rx) = watch::channel(Arc::new(config));
+    tokio::spawn(config::watch(config_tx, config_valid, ws_url.is_some(), event_loop.create_proxy()));
     let mut main_window = None::<(Rc<Window>, softbuffer::Surface<Rc<Window>, Rc<Window>>)>;
-    if mock_state {
-        cache.state = State::BinaryTime(chrono_tz::Etc::UTC);
+    if calibrate {
+        cache.state = State::Calibration;
+    } else if let Some(mock_state) = mock_state {
+        cache.clock.resolve(&mock_state.timezone().unwrap_or(chrono_tz::Etc::UTC));
+        cache.state = mock_state.into();
     } else {
-        tokio::spawn(state::maintain(SmallRng::from_entropy(), http_client, mock_event, !no_self_update, ws_url, event_loop.create_proxy()));
+        let seed = if let Some(seed) = seed {
+            eprintln!("using seed {seed}");
+            seed
+        } else {
+            let seed = random();
+            eprintln!("using random seed {seed}");
+            seed
+        };
+        let maintain = state::maintain(SmallRng::seed_from_u64(seed), http_client, config_rx.clone(), cache.clock.clone(), mock_event, !no_self_update, install_paths.clone(), ws_url, event_loop.create_proxy());
+        tokio::spawn(async move {
+            // the scheduler needs the API key, so it waits for the config file to be fixed
+            if !config_valid && config_rx.changed().await.is_err() { return }
+            maintain.await
+        });
+    }
+    #[cfg(unix)] {
+        let install_paths = install_paths.clone();
+        tokio::spawn(async move {
+            // systemd stops sil with SIGTERM, which is a clean shutdown as far as rollbacks are concerned
+            match signal(SignalKind::terminate()) {
+                Ok(mut sigterm) => if sigterm.recv().await.is_some() {
+                    if let Err(e) = install::stopped(&install_paths).await {
+                        eprintln!("failed to record clean shutdown: {e} ({e:?})");
+                    }
+                    process::exit(0)
+                },
+                Err(e) => eprintln!("failed to listen for SIGTERM: {e} ({e:?})"),
+            }
+        });
     }
+    #[cfg(unix)] let stopped_paths = install_paths.clone();
     let (exit_code_tx, mut exit_code_rx) = oneshot::channel();
     let mut exit_code_tx = Some(exit_code_tx);
     #[allow(deprecated)] /*TODO event_loop.run_app*/ let event_loop_result = tokio::task::block_in_place(move || event_loop.run(move |event, target| {
@@ -380,10 +736,24 @@ async fn main(Args { light, mock_event, mock_state, no_self_update, windowed, ws
                             target.exit();
                             return
                         }
+                        WindowEvent::KeyboardInput { event: KeyEvent { logical_key: Key::Named(NamedKey::F3), state: ElementState::Pressed, repeat: false, .. }, .. } => {
+                            cache.debug_overlay = !cache.debug_overlay;
+                            window.request_redraw();
+                        }
                         WindowEvent::RedrawRequested => {
+                            let frame_start = Instant::now();
                             let PhysicalSize { width, height } = window.inner_size();
-                            if width != cache.canvas.width() || height != cache.canvas.height() {
-                                match Pixmap::new(width, height) {
+                            // e.g. while minimized, there's nothing to draw or present
+                            if width == 0 || height == 0 { return }
+                            if (width, height) != cache.screen_size {
+                                cache.screen_size = (width, height);
+                                if let (Some(width), Some(height)) = (NonZero::new(width), NonZero::new(height)) {
+                                    winit_try!(surface.resize(width, height), "failed to resize the screen buffer");
+                                }
+                            }
+                            let (canvas_width, canvas_height) = cache.output.canvas_size(width, height);
+                            if canvas_width != cache.canvas.width() || canvas_height != cache.canvas.height() {
+                                match Pixmap::new(canvas_width, canvas_height) {
                                     Some(new_canvas) => cache.canvas = new_canvas,
                                     None => {
                                         eprintln!("failed to create a new canvas");
@@ -394,22 +764,58 @@ async fn main(Args { light, mock_event, mock_state, no_self_update, windowed, ws
                                         return
                                     }
                                 }
-                                if let (Some(width), Some(height)) = (NonZero::new(width), NonZero::new(height)) {
-                                    winit_try!(surface.resize(width, height), "failed to resize the screen buffer");
-                                }
                             }
-                            winit_try!(cache.draw(), "failed to draw to the canvas");
+                            cache.draw();
+                            let (effects, until_effects_change) = cache.output.burn_in.effects(cache.clock.now().with_timezone(&Local));
+                            if let Some(until_effects_change) = until_effects_change {
+                                cache.redraw_after(until_effects_change);
+                            }
                             let buffer = winit_try!(surface.buffer_mut(), "failed to get the screen buffer");
-                            winit_try!(pixmap_to_softbuf(cache.canvas.as_ref(), buffer), "failed to present the screen buffer");
+                            let background = cache.background();
+                            winit_try!(cache.output.present(cache.canvas.as_ref(), background, effects, &mut cache.pixel_map, buffer, width, height), "failed to present the screen buffer");
+                            cache.frame_time = Some(frame_start.elapsed());
                         }
                         _ => {} //TODO handle more events (which?)
                     }
                 }
             },
             Event::UserEvent(event) => {
+                let mut redraw = true;
                 match event {
-                    UserEvent::State(state) => cache.state = state,
-                    UserEvent::Logo(img) => cache.logo = Some(img),
+                    UserEvent::State(state) => {
+                        // the scheduler resends the current state on every rotation, and the digits are still cached then
+                        let mode_changed = mem::discriminant(&state) != mem::discriminant(&cache.state);
+                        cache.state = state;
+                        if mode_changed {
+                            cache.prewarm();
+                        }
+                    }
+                    UserEvent::Clock(clock) => {
+                        cache.clock = clock;
+                    }
+                    UserEvent::Icon(name, image) => {
+                        cache.renderer.icons.insert(name, image);
+                    }
+                    UserEvent::Status(status) => {
+                        cache.status = *status;
+                        redraw = cache.debug_overlay;
+                    }
+                    UserEvent::Config(config, restart_required) => {
+                        cache.dark = config.theme.with_flags(light, dark) == Theme::Dark;
+                        // the canvas is resized on the next redraw if necessary
+                        cache.output = output(&config.display, safe_area, rotate, mirror, render_scale);
+                        cache.renderer.scale = cache.output.render_scale();
+                        cache.display = config.display.description();
+                        if restart_required.iter().any(|field| field.starts_with("display.")) {
+                            cache.display.push_str(" (restart to apply)");
+                        }
+                        cache.config_error = None;
+                        cache.restart_required = restart_required;
+                    }
+                    UserEvent::ConfigError(e) => {
+                        eprintln!("failed to reload config: {e}");
+                        cache.config_error = Some(e);
+                    }
                     UserEvent::UpdateDone => {
                         #[cfg(feature = "nixos")] {
                             if let Some(exit_code_tx) = exit_code_tx.take() {
@@ -419,17 +825,21 @@ async fn main(Args { light, mock_event, mock_state, no_self_update, windowed, ws
                         }
                         #[cfg(not(feature = "nixos"))] {
                             #[cfg(unix)] {
-                                let e = process::Command::new(if current_exe == Path::new(BIN_PATH) && Path::new(REIWA_BIN_PATH).exists() { REIWA_BIN_PATH } else { BIN_PATH }).exec();
+                                let e = process::Command::new(install_paths.restart_target(&current_exe)).exec();
                                 cache.state = State::Error(Arc::new(e.into()));
                             }
                             #[cfg(not(unix))] {
-                                cache.state = State::Logo { msg: "restart to update" };
+                                cache.state = State::Logo { msg: Cow::Borrowed("restart to update") };
                             }
                         }
                     }
                 }
-                if let Some((ref window, _)) = main_window {
-                    window.request_redraw();
+                if_chain! {
+                    if redraw;
+                    if let Some((ref window, _)) = main_window;
+                    then {
+                        window.request_redraw();
+                    }
                 }
             }
             Event::Resumed => if main_window.is_none() {
@@ -441,7 +851,9 @@ async fn main(Args { light, mock_event, mock_state, no_self_update, windowed, ws
                     if let Some(monitor) = target.primary_monitor().or_else(|| target.available_monitors().max_by_key(|monitor| (monitor.size().width * monitor.size().height, monitor.refresh_rate_millihertz())));
                     if let Some(video_mode) = monitor.video_modes().min_by_key(|video_mode| (video_mode.size().width.abs_diff(monitor.size().width) + video_mode.size().height.abs_diff(monitor.size().height), -(video_mode.refresh_rate_millihertz() as i32)));
                     then {
-                        cache.canvas = winit_try!(Pixmap::new(video_mode.size().width, video_mode.size().height).ok_or(Error::Pixmap), "failed to create new canvas");
+                        let (canvas_width, canvas_height) = cache.output.canvas_size(video_mode.size().width, video_mode.size().height);
+                        cache.canvas = winit_try!(Pixmap::new(canvas_width, canvas_height).ok_or(Error::Pixmap), "failed to create new canvas");
+                        cache.screen_size = (video_mode.size().width, video_mode.size().height);
                         let size = (NonZero::new(video_mode.size().width), NonZero::new(video_mode.size().height));
                         if !windowed {
                             window_attributes.fullscreen = {
@@ -479,9 +891,47 @@ async fn main(Args { light, mock_event, mock_state, no_self_update, windowed, ws
         }
         target.set_control_flow(cache.redraw_at);
     }));
-    match event_loop_result {
-        Ok(()) => Ok(exit_code_rx.try_recv().unwrap_or_default()),
-        Err(winit::error::EventLoopError::ExitFailure(code)) => Ok(code),
-        Err(e) => Err(e.into()),
+    let exit_code = match event_loop_result {
+        Ok(()) => exit_code_rx.try_recv().unwrap_or_default(),
+        Err(winit::error::EventLoopError::ExitFailure(code)) => code,
+        Err(e) => return Err(e.into()),
+    };
+    #[cfg(unix)] if exit_code == 0 {
+        install::stopped(&stopped_paths).await?;
+    }
+    Ok(exit_code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(h: u32, m: u32, s: u32, nano: u32) -> NaiveTime {
+        NaiveTime::from_hms_nano_opt(h, m, s, nano).expect("invalid test time")
+    }
+
+    #[test]
+    fn binary_time_boundaries() {
+        // each bit pattern is shown for exactly 1.318359375 seconds
+        assert_eq!(binary_time(time(0, 0, 0, 0)), (0, Duration::from_nanos(1_318_359_375)));
+        assert_eq!(binary_time(time(0, 0, 1, 318_359_374)), (0, Duration::from_nanos(1)));
+        assert_eq!(binary_time(time(0, 0, 1, 318_359_375)), (1, Duration::from_nanos(1_318_359_375)));
+        assert_eq!(binary_time(time(12, 0, 0, 0)), (0x8000, Duration::from_nanos(1_318_359_375)));
+        assert_eq!(binary_time(time(23, 59, 58, 681_640_625)), (0xffff, Duration::from_nanos(1_318_359_375)));
+        assert_eq!(binary_time(time(23, 59, 59, 999_000_000)), (0xffff, Duration::from_millis(1)));
+        assert_eq!(binary_time(time(23, 59, 59, 999_999_999)), (0xffff, Duration::from_nanos(1)));
+    }
+
+    #[test]
+    fn binary_time_leap_second() {
+        assert_eq!(binary_time(time(23, 59, 59, 1_500_000_000)), (0xffff, Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn bare_mock_state() {
+        let args = <Args as clap::Parser>::try_parse_from(["sil", "--mock-state"]).expect("failed to parse arguments");
+        assert_eq!(args.mock_state, Some(Vec::default()));
+        let args = <Args as clap::Parser>::try_parse_from(["sil", "--windowed", "--mock-state", "new-year", "--tz", "Pacific/Auckland"]).expect("failed to parse arguments");
+        assert_eq!(args.mock_state, Some(vec!["new-year".to_owned(), "--tz".to_owned(), "Pacific/Auckland".to_owned()]));
     }
 }
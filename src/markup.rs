@@ -0,0 +1,446 @@
+//! Text rendering with a small markup language that allows mixing styles within one layout.
+//!
+//! Supported markup:
+//!
+//! * `**bold**`
+//! * `*emphasis*` (drawn in the accent color)
+//! * `{color=#rrggbb}…{/color}` (also `#rgb`)
+//! * `{size=1.5}…{/size}` (relative to the base size, limited to between 0.1 and 4 times the base size)
+//! * `{br}` or an actual newline for a line break
+//! * `{icon=logo}` for an inline icon
+//! * `\` to escape the next character
+//!
+//! Unknown or malformed tags are displayed literally, as are `*` and `**` that aren't next to the text they enclose, like in `5 * 3`, or that are never closed.
+
+use {
+    std::collections::HashMap,
+    fontdue::layout::{
+        CoordinateSystem,
+        GlyphRasterConfig,
+        HorizontalAlign,
+        Layout,
+        LayoutSettings,
+        TextStyle,
+        VerticalAlign,
+    },
+    tiny_skia::*,
+    crate::{
+        font::{
+            Face,
+            Fonts,
+        },
+        glyph_cache::GlyphCache,
+        image::Image,
+    },
+};
+
+/// The range of sizes `{size=…}` can produce, relative to the base size, so nested tags can't make glyphs arbitrarily large.
+const MIN_RELATIVE_SIZE: f32 = 0.1;
+const MAX_RELATIVE_SIZE: f32 = 4.0;
+
+/// Laid out in place of an inline icon. Its advance width is 1em, which is also the size of the icon.
+const ICON_PLACEHOLDER: &str = "\u{2003}";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Style {
+    bold: bool,
+    emphasis: bool,
+    color: Option<Color>,
+    size: f32,
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            bold: false,
+            emphasis: false,
+            color: None,
+            size: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Text {
+        text: String,
+        style: Style,
+    },
+    Icon {
+        name: String,
+        size: f32,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Text {
+    items: Vec<Item>,
+}
+
+impl Text {
+    /// Text without any markup, e.g. an error message.
+    pub(crate) fn plain(text: impl Into<String>) -> Self {
+        Self {
+            items: vec![Item::Text { text: text.into(), style: Style::default() }],
+        }
+    }
+
+    /// Appends text without markup, e.g. details that shouldn't be interpreted as markup.
+    pub(crate) fn push_plain(&mut self, text: impl Into<String>) {
+        self.items.push(Item::Text { text: text.into(), style: Style::default() });
+    }
+
+    pub(crate) fn parse(markup: &str) -> Self {
+        fn flush(items: &mut Vec<Item>, buf: &mut String, style: Style) {
+            if !buf.is_empty() {
+                items.push(Item::Text { text: buf.split_off(0), style });
+            }
+        }
+
+        let mut items = Vec::default();
+        let mut buf = String::default();
+        let mut style = Style::default();
+        let mut colors = Vec::default();
+        let mut sizes = Vec::default();
+        let mut chars = markup.char_indices().peekable();
+        while let Some((idx, c)) = chars.next() {
+            match c {
+                '\\' => if let Some((_, escaped)) = chars.next() {
+                    buf.push(escaped);
+                },
+                '*' => {
+                    let delimiter = if markup[idx + 1..].starts_with('*') { "**" } else { "*" };
+                    let is_open = if delimiter == "**" { style.bold } else { style.emphasis };
+                    let after = idx + delimiter.len();
+                    let toggles = if is_open {
+                        markup[..idx].chars().next_back().is_some_and(|prev| !prev.is_whitespace())
+                    } else {
+                        markup[after..].chars().next().is_some_and(|next| !next.is_whitespace()) && has_closer(&markup[after..], delimiter)
+                    };
+                    if toggles {
+                        flush(&mut items, &mut buf, style);
+                        if delimiter == "**" {
+                            style.bold = !style.bold;
+                        } else {
+                            style.emphasis = !style.emphasis;
+                        }
+                    } else {
+                        buf.push_str(delimiter);
+                    }
+                    while chars.next_if(|&(next_idx, _)| next_idx < after).is_some() {}
+                }
+                '{' => {
+                    let Some(len) = markup[idx..].find('}') else {
+                        buf.push(c);
+                        continue
+                    };
+                    let tag = &markup[idx + 1..idx + len];
+                    let known = match tag.split_once('=') {
+                        None => match tag {
+                            "br" => {
+                                buf.push('\n');
+                                true
+                            }
+                            "/color" => if let Some(color) = colors.pop() {
+                                flush(&mut items, &mut buf, style);
+                                style.color = color;
+                                true
+                            } else {
+                                false
+                            },
+                            "/size" => if let Some(size) = sizes.pop() {
+                                flush(&mut items, &mut buf, style);
+                                style.size = size;
+                                true
+                            } else {
+                                false
+                            },
+                            _ => false,
+                        },
+                        Some(("color", color)) => if let Some(color) = parse_color(color) {
+                            flush(&mut items, &mut buf, style);
+                            colors.push(style.color.replace(color));
+                            true
+                        } else {
+                            false
+                        },
+                        Some(("size", size)) => if let Some(size) = size.parse::<f32>().ok().filter(|size| size.is_finite() && *size > 0.0) {
+                            flush(&mut items, &mut buf, style);
+                            sizes.push(style.size);
+                            style.size = (style.size * size).clamp(MIN_RELATIVE_SIZE, MAX_RELATIVE_SIZE);
+                            true
+                        } else {
+                            false
+                        },
+                        Some(("icon", name)) => {
+                            flush(&mut items, &mut buf, style);
+                            items.push(Item::Icon { name: name.to_owned(), size: style.size });
+                            true
+                        }
+                        Some(_) => false,
+                    };
+                    if known {
+                        while chars.next_if(|&(next_idx, _)| next_idx <= idx + len).is_some() {}
+                    } else {
+                        buf.push(c);
+                    }
+                }
+                _ => buf.push(c),
+            }
+        }
+        flush(&mut items, &mut buf, style);
+        Self { items }
+    }
+}
+
+/// Whether `rest` contains a `*` or `**` (depending on `delimiter`) that can close it, tokenized the same way as in [`Text::parse`]:
+/// escaped asterisks don't count, and neither does half of a `**` when looking for a `*`.
+fn has_closer(rest: &str, delimiter: &str) -> bool {
+    let mut chars = rest.char_indices().peekable();
+    let mut prev = None;
+    while let Some((idx, c)) = chars.next() {
+        match c {
+            '\\' => prev = chars.next().map(|(_, escaped)| escaped),
+            '*' => {
+                let found = if rest[idx + 1..].starts_with('*') {
+                    chars.next();
+                    "**"
+                } else {
+                    "*"
+                };
+                if found == delimiter && prev.is_some_and(|prev: char| !prev.is_whitespace()) { return true }
+                prev = Some('*');
+            }
+            _ => prev = Some(c),
+        }
+    }
+    false
+}
+
+fn parse_color(color: &str) -> Option<Color> {
+    let hex = color.strip_prefix('#')?;
+    let digit = |idx: usize, len: usize| u8::from_str_radix(hex.get(idx..idx + len)?, 16).ok();
+    match hex.len() {
+        3 => Some(Color::from_rgba8(digit(0, 1)? * 0x11, digit(1, 1)? * 0x11, digit(2, 1)? * 0x11, 0xff)),
+        6 => Some(Color::from_rgba8(digit(0, 2)?, digit(2, 2)?, digit(4, 2)?, 0xff)),
+        _ => None,
+    }
+}
+
+#[derive(Clone, Copy)]
+struct GlyphData {
+    color: ColorU8,
+    /// Index into `Text::items`
+    icon: Option<usize>,
+}
+
+/// Everything needed to draw text that can be reused between frames.
+pub(crate) struct Renderer {
+    pub(crate) fonts: Fonts,
+    pub(crate) glyph_cache: GlyphCache,
+    pub(crate) icons: HashMap<String, Image>,
+    /// All text sizes are multiplied by this, so layouts look the same at any render scale.
+    pub(crate) scale: f32,
+    layout: Layout<GlyphData>,
+}
+
+impl Renderer {
+    pub(crate) fn new(fonts: Fonts, glyph_cache: GlyphCache) -> Self {
+        Self {
+            icons: HashMap::default(),
+            scale: 1.0,
+            layout: Layout::new(CoordinateSystem::PositiveYDown),
+            fonts, glyph_cache,
+        }
+    }
+}
+
+pub(crate) struct Builder<'a> {
+    text: &'a Text,
+    face: Face,
+    size: f32,
+    color: Color,
+    accent: Option<Color>,
+    halign: HorizontalAlign,
+    valign: VerticalAlign,
+}
+
+impl<'a> Builder<'a> {
+    pub(crate) fn new(text: &'a Text) -> Self {
+        Self {
+            face: Face::Regular,
+            size: 24.0,
+            color: Color::BLACK,
+            accent: None,
+            halign: HorizontalAlign::Center,
+            valign: VerticalAlign::Middle,
+            text,
+        }
+    }
+
+    pub(crate) fn face(mut self, face: Face) -> Self {
+        self.face = face;
+        self
+    }
+
+    pub(crate) fn size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub(crate) fn color(mut self, color: Color) -> Self {
+        self.color = color;
+        self
+    }
+
+    /// The color for `*emphasized*` text. Defaults to the regular text color.
+    pub(crate) fn accent(mut self, accent: Color) -> Self {
+        self.accent = Some(accent);
+        self
+    }
+
+    pub(crate) fn halign(mut self, halign: HorizontalAlign) -> Self {
+        self.halign = halign;
+        self
+    }
+
+    pub(crate) fn valign(mut self, valign: VerticalAlign) -> Self {
+        self.valign = valign;
+        self
+    }
+
+    pub(crate) fn draw(self, renderer: &mut Renderer, mut canvas: PixmapMut<'_>) {
+        renderer.layout.reset(&LayoutSettings {
+            max_width: Some(canvas.width() as f32),
+            max_height: Some(canvas.height() as f32),
+            horizontal_align: self.halign,
+            vertical_align: self.valign,
+            ..LayoutSettings::default()
+        });
+        let fonts = [&renderer.fonts.regular, &renderer.fonts.bold, &renderer.fonts.display];
+        let base_size = self.size * renderer.scale;
+        for (idx, item) in self.text.items.iter().enumerate() {
+            match item {
+                Item::Text { text, style } => {
+                    let face = if style.bold { Face::Bold } else { self.face };
+                    let color = style.color.unwrap_or(if style.emphasis { self.accent.unwrap_or(self.color) } else { self.color });
+                    renderer.layout.append(&fonts, &TextStyle::with_user_data(text, base_size * style.size, face as usize, GlyphData { color: color.to_color_u8(), icon: None }));
+                }
+                Item::Icon { size, .. } => renderer.layout.append(&fonts, &TextStyle::with_user_data(ICON_PLACEHOLDER, base_size * size, self.face as usize, GlyphData { color: self.color.to_color_u8(), icon: Some(idx) })),
+            }
+        }
+        let lines = renderer.layout.lines().map(Vec::as_slice).unwrap_or_default();
+        for (idx, glyph) in renderer.layout.glyphs().iter().enumerate() {
+            if let Some(icon) = glyph.user_data.icon {
+                let Item::Icon { ref name, .. } = self.text.items[icon] else { unreachable!("icon glyph for text item") };
+                let Some(image) = renderer.icons.get_mut(name) else { continue };
+                let Some(line) = lines.iter().find(|line| (line.glyph_start..=line.glyph_end).contains(&idx)) else { continue };
+                let size = glyph.key.px;
+                image.draw_fit(&mut canvas, glyph.x, line.baseline_y - 0.8 * size, size, size);
+            } else if glyph.char_data.rasterize() {
+                let font = fonts[glyph.font_index];
+                if let Some(pixmap) = renderer.glyph_cache.get(glyph.key, glyph.user_data.color, || rasterize(font, glyph.key, glyph.user_data.color)) {
+                    canvas.draw_pixmap(glyph.x as i32, glyph.y as i32, pixmap.as_ref(), &PixmapPaint::default(), Transform::identity(), None);
+                }
+            }
+        }
+    }
+}
+
+fn rasterize(font: &fontdue::Font, config: GlyphRasterConfig, color: ColorU8) -> Option<Pixmap> {
+    let (metrics, coverage) = font.rasterize_config(config);
+    let mut pixmap = Pixmap::new(metrics.width.try_into().ok()?, metrics.height.try_into().ok()?)?;
+    for (pixel, coverage) in pixmap.pixels_mut().iter_mut().zip(coverage) {
+        let alpha = (u16::from(color.alpha()) * u16::from(coverage) / 255) as u8;
+        *pixel = ColorU8::from_rgba(color.red(), color.green(), color.blue(), alpha).premultiply();
+    }
+    Some(pixmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(text: &str, style: Style) -> Item {
+        Item::Text { text: text.to_owned(), style }
+    }
+
+    fn parse(markup: &str) -> Vec<Item> {
+        Text::parse(markup).items
+    }
+
+    const BOLD: Style = Style { bold: true, emphasis: false, color: None, size: 1.0 };
+    const EMPHASIS: Style = Style { bold: false, emphasis: true, color: None, size: 1.0 };
+
+    #[test]
+    fn plain() {
+        assert_eq!(parse("Es ist 22:00 Uhr."), vec![text("Es ist 22:00 Uhr.", Style::default())]);
+        assert_eq!(parse(""), Vec::default());
+    }
+
+    #[test]
+    fn bold_and_emphasis() {
+        assert_eq!(parse("**a** *b*"), vec![text("a", BOLD), text(" ", Style::default()), text("b", EMPHASIS)]);
+        assert_eq!(parse("*a **b** c*"), vec![
+            text("a ", EMPHASIS),
+            text("b", Style { bold: true, ..EMPHASIS }),
+            text(" c", EMPHASIS),
+        ]);
+    }
+
+    #[test]
+    fn literal_asterisks() {
+        assert_eq!(parse("5 * 3"), vec![text("5 * 3", Style::default())]);
+        assert_eq!(parse("5*3"), vec![text("5*3", Style::default())]);
+        assert_eq!(parse("a ** b"), vec![text("a ** b", Style::default())]);
+        assert_eq!(parse("*a * b*"), vec![text("a * b", EMPHASIS)]);
+        assert_eq!(parse("**a*"), vec![text("**a*", Style::default())]);
+        // an escaped asterisk or half of a `**` doesn't close emphasis
+        assert_eq!(parse(r"*a \*"), vec![text("*a *", Style::default())]);
+        assert_eq!(parse("*a **b**"), vec![text("*a ", Style::default()), text("b", BOLD)]);
+        assert_eq!(parse("**a \\** b"), vec![text("**a ** b", Style::default())]);
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(parse(r"\*a\* \{br} \\"), vec![text(r"*a* {br} \", Style::default())]);
+        assert_eq!(parse("trailing\\"), vec![text("trailing", Style::default())]);
+    }
+
+    #[test]
+    fn nesting() {
+        let red = Color::from_rgba8(0xff, 0x00, 0x00, 0xff);
+        let blue = Color::from_rgba8(0x00, 0x00, 0xff, 0xff);
+        assert_eq!(parse("{color=#f00}a{color=#0000ff}b{/color}c{/color}d"), vec![
+            text("a", Style { color: Some(red), ..Style::default() }),
+            text("b", Style { color: Some(blue), ..Style::default() }),
+            text("c", Style { color: Some(red), ..Style::default() }),
+            text("d", Style::default()),
+        ]);
+        assert_eq!(parse("{size=2}a{size=0.5}b{/size}{icon=logo}{/size}c"), vec![
+            text("a", Style { size: 2.0, ..Style::default() }),
+            text("b", Style::default()),
+            Item::Icon { name: "logo".to_owned(), size: 2.0 },
+            text("c", Style::default()),
+        ]);
+        assert_eq!(parse("a{br}b"), vec![text("a\nb", Style::default())]);
+    }
+
+    #[test]
+    fn size_is_clamped() {
+        assert_eq!(parse("{size=1000}{size=1000}a{/size}b{/size}c"), vec![
+            text("a", Style { size: MAX_RELATIVE_SIZE, ..Style::default() }),
+            text("b", Style { size: MAX_RELATIVE_SIZE, ..Style::default() }),
+            text("c", Style::default()),
+        ]);
+        assert_eq!(parse("{size=0.001}a"), vec![text("a", Style { size: MIN_RELATIVE_SIZE, ..Style::default() })]);
+    }
+
+    #[test]
+    fn malformed_tags() {
+        assert_eq!(parse("{unknown}a{color=red}b{size=-1}"), vec![text("{unknown}a{color=red}b{size=-1}", Style::default())]);
+        assert_eq!(parse("a{/color}b{/size}"), vec![text("a{/color}b{/size}", Style::default())]);
+        assert_eq!(parse("unclosed {br"), vec![text("unclosed {br", Style::default())]);
+        assert_eq!(parse("{color=#f00"), vec![text("{color=#f00", Style::default())]);
+    }
+}
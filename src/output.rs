@@ -0,0 +1,724 @@
+//! Mapping the canvas that states are drawn on onto the screen.
+
+use {
+    std::{
+        fmt,
+        str::FromStr,
+        time::Duration,
+    },
+    chrono::{
+        TimeDelta,
+        prelude::*,
+    },
+    serde::{
+        Deserialize,
+        Deserializer,
+        de::Error as _,
+    },
+    softbuffer::SoftBufferError,
+    tiny_skia::{
+        PixmapRef,
+        PremultipliedColorU8,
+    },
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Inset {
+    Pixels(u32),
+    Percent(f32),
+}
+
+impl Inset {
+    fn resolve(&self, screen_size: u32) -> u32 {
+        match *self {
+            Self::Pixels(px) => px,
+            Self::Percent(percent) => (screen_size as f32 * percent / 100.0).round() as u32,
+        }
+    }
+}
+
+impl FromStr for Inset {
+    type Err = SafeAreaParseError;
+
+    fn from_str(s: &str) -> Result<Self, SafeAreaParseError> {
+        if let Some(percent) = s.strip_suffix('%') {
+            let percent = percent.parse::<f32>().map_err(|_| SafeAreaParseError::Inset(s.to_owned()))?;
+            if (0.0..50.0).contains(&percent) {
+                Ok(Self::Percent(percent))
+            } else {
+                Err(SafeAreaParseError::Inset(s.to_owned()))
+            }
+        } else {
+            Ok(Self::Pixels(s.strip_suffix("px").unwrap_or(s).parse().map_err(|_| SafeAreaParseError::Inset(s.to_owned()))?))
+        }
+    }
+}
+
+impl fmt::Display for Inset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Pixels(px) => write!(f, "{px}px"),
+            Self::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum SafeAreaParseError {
+    #[error("expected 1 to 4 insets, got {0}")]
+    Count(usize),
+    #[error("invalid inset {0:?}, expected pixels (e.g. 20 or 20px) or a percentage below 50% (e.g. 2.5%)")]
+    Inset(String),
+}
+
+/// How far the content is kept from each edge of the screen, to compensate for projectors and TVs cropping the edges (overscan).
+///
+/// Parsed like a CSS `margin`: 1 to 4 insets separated by spaces or commas, in the order top, right, bottom, left.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct SafeArea {
+    pub(crate) top: Inset,
+    pub(crate) right: Inset,
+    pub(crate) bottom: Inset,
+    pub(crate) left: Inset,
+}
+
+impl Default for SafeArea {
+    fn default() -> Self {
+        Self {
+            top: Inset::Pixels(0),
+            right: Inset::Pixels(0),
+            bottom: Inset::Pixels(0),
+            left: Inset::Pixels(0),
+        }
+    }
+}
+
+impl FromStr for SafeArea {
+    type Err = SafeAreaParseError;
+
+    fn from_str(s: &str) -> Result<Self, SafeAreaParseError> {
+        let insets = s.split([' ', ',']).filter(|inset| !inset.is_empty()).map(Inset::from_str).collect::<Result<Vec<_>, _>>()?;
+        Ok(match insets[..] {
+            [all] => Self { top: all, right: all, bottom: all, left: all },
+            [vertical, horizontal] => Self { top: vertical, right: horizontal, bottom: vertical, left: horizontal },
+            [top, horizontal, bottom] => Self { top, right: horizontal, bottom, left: horizontal },
+            [top, right, bottom, left] => Self { top, right, bottom, left },
+            _ => return Err(SafeAreaParseError::Count(insets.len())),
+        })
+    }
+}
+
+impl fmt::Display for SafeArea {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {} {}", self.top, self.right, self.bottom, self.left)
+    }
+}
+
+impl<'de> Deserialize<'de> for SafeArea {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(D::Error::custom)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("invalid rotation {0:?}, expected 0, 90, 180, or 270")]
+pub(crate) struct RotationParseError(String);
+
+/// Clockwise rotation of the canvas on the screen, e.g. 180° for a ceiling-mounted projector.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Rotation {
+    #[default]
+    None,
+    Quarter,
+    Half,
+    ThreeQuarters,
+}
+
+impl Rotation {
+    fn swaps_axes(&self) -> bool {
+        matches!(self, Self::Quarter | Self::ThreeQuarters)
+    }
+}
+
+impl FromStr for Rotation {
+    type Err = RotationParseError;
+
+    fn from_str(s: &str) -> Result<Self, RotationParseError> {
+        match s.strip_suffix('°').unwrap_or(s) {
+            "0" => Ok(Self::None),
+            "90" => Ok(Self::Quarter),
+            "180" => Ok(Self::Half),
+            "270" => Ok(Self::ThreeQuarters),
+            _ => Err(RotationParseError(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::None => write!(f, "0°"),
+            Self::Quarter => write!(f, "90°"),
+            Self::Half => write!(f, "180°"),
+            Self::ThreeQuarters => write!(f, "270°"),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Rotation {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u16::deserialize(deserializer)?.to_string().parse().map_err(D::Error::custom)
+    }
+}
+
+/// Mirroring of the canvas on the screen, e.g. horizontal for a rear-projection screen. Applied after rotation.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Mirror {
+    #[default]
+    None,
+    Horizontal,
+    Vertical,
+    Both,
+}
+
+impl Mirror {
+    fn horizontal(&self) -> bool {
+        matches!(self, Self::Horizontal | Self::Both)
+    }
+
+    fn vertical(&self) -> bool {
+        matches!(self, Self::Vertical | Self::Both)
+    }
+}
+
+/// Settings to reduce burn-in on displays that show mostly static content for days.
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct BurnIn {
+    /// Maximum distance in pixels by which the whole layout drifts from its original position. 0 disables pixel shifting.
+    ///
+    /// The viewport is shrunk by this distance on every side so the layout can drift in every direction without being cropped.
+    #[serde(default)]
+    pub(crate) pixel_shift: u32,
+    /// How long the layout stays in place before moving by one pixel.
+    #[serde(default = "default_pixel_shift_interval_secs")]
+    pub(crate) pixel_shift_interval_secs: u64,
+    #[serde(default)]
+    pub(crate) invert: Option<Invert>,
+    #[serde(default)]
+    pub(crate) dim: Option<Dim>,
+}
+
+fn default_pixel_shift_interval_secs() -> u64 { 60 }
+
+/// Periodically displays the screen in inverted colors.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Invert {
+    pub(crate) every_mins: u64,
+    pub(crate) for_secs: u64,
+}
+
+/// Reduces brightness during the given time of day (local time of the device), e.g. overnight.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct Dim {
+    pub(crate) from: NaiveTime,
+    pub(crate) to: NaiveTime,
+    /// Between 0 (black) and 1 (unchanged).
+    pub(crate) brightness: f32,
+}
+
+/// How the canvas is modified when composing a particular frame.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Effects {
+    offset: (i32, i32),
+    invert: bool,
+    brightness: f32,
+}
+
+impl Default for Effects {
+    fn default() -> Self {
+        Self {
+            offset: (0, 0),
+            invert: false,
+            brightness: 1.0,
+        }
+    }
+}
+
+impl BurnIn {
+    /// Returns the effects to apply at the given time and how long until they next change. Dimming uses the time of day in the timezone of `now`.
+    pub(crate) fn effects<Z: TimeZone>(&self, now: DateTime<Z>) -> (Effects, Option<Duration>) {
+        let mut effects = Effects::default();
+        let mut until_change = None::<Duration>;
+        let mut change_in = |duration: Duration| until_change = Some(until_change.map_or(duration, |prev| prev.min(duration)));
+        let millis = u64::try_from(now.timestamp_millis()).unwrap_or_default();
+        if self.pixel_shift > 0 {
+            let interval = self.pixel_shift_interval_secs.max(1) * 1000;
+            effects.offset = pixel_shift_offset(self.pixel_shift, millis / interval);
+            change_in(Duration::from_millis(interval - millis % interval));
+        }
+        if let Some(Invert { every_mins, for_secs }) = self.invert {
+            let interval = every_mins.max(1) * 60 * 1000;
+            let duration = (for_secs * 1000).min(interval);
+            let phase = millis % interval;
+            effects.invert = phase < duration;
+            change_in(Duration::from_millis(if effects.invert { duration - phase } else { interval - phase }));
+        }
+        if let Some(Dim { from, to, brightness }) = self.dim {
+            let time = now.naive_local().time();
+            let dimmed = if from <= to {
+                from <= time && time < to
+            } else {
+                from <= time || time < to
+            };
+            if dimmed {
+                effects.brightness = if brightness.is_finite() { brightness.clamp(0.0, 1.0) } else { 1.0 };
+            }
+            for boundary in [from, to] {
+                let mut delta = boundary - time;
+                if delta <= TimeDelta::zero() {
+                    delta += TimeDelta::days(1);
+                }
+                if let Ok(delta) = delta.to_std() {
+                    change_in(delta);
+                }
+            }
+        }
+        (effects, until_change)
+    }
+}
+
+/// Walks back and forth along a boustrophedon path through the square of possible offsets, so each step moves the layout by exactly one pixel.
+fn pixel_shift_offset(max: u32, step: u64) -> (i32, i32) {
+    let side = u64::from(max) * 2 + 1;
+    let cells = side * side;
+    let step = step % (2 * cells - 2).max(1);
+    let cell = if step < cells { step } else { 2 * cells - 2 - step };
+    let row = cell / side;
+    let col = if row % 2 == 0 { cell % side } else { side - 1 - cell % side };
+    (col as i32 - max as i32, row as i32 - max as i32)
+}
+
+/// The part of the screen the canvas is drawn to, in logical (unrotated) coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Viewport {
+    pub(crate) x: u32,
+    pub(crate) y: u32,
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+}
+
+/// Which canvas pixel each screen pixel shows, for composing rotated, mirrored, or scaled frames without per-pixel arithmetic.
+///
+/// Each screen column and row determines the position along one axis of the canvas, so the index of the source pixel is the sum of a per-column and a per-row offset.
+/// The offsets are only recomputed when the screen size, canvas size, viewport, or orientation changes.
+#[derive(Debug, Default)]
+pub(crate) struct PixelMap {
+    key: Option<(u32, u32, u32, u32, Viewport, Rotation, Mirror)>,
+    /// Offset into the canvas pixels for each screen column, `None` outside the viewport.
+    columns: Vec<Option<usize>>,
+    /// Offset into the canvas pixels for each screen row, `None` outside the viewport.
+    rows: Vec<Option<usize>>,
+}
+
+impl PixelMap {
+    fn update(&mut self, output: &Output, viewport: Viewport, canvas_width: u32, canvas_height: u32, screen_width: u32, screen_height: u32) {
+        let key = (screen_width, screen_height, canvas_width, canvas_height, viewport, output.rotation, output.mirror);
+        if self.key == Some(key) { return }
+        self.key = Some(key);
+        // nearest-neighbour scaling from the viewport to the canvas, which is smaller than the viewport if a render scale is configured
+        let canvas_x = |logical_x: u32| logical_x.checked_sub(viewport.x).filter(|&viewport_x| viewport_x < viewport.width).map(|viewport_x| (u64::from(viewport_x) * u64::from(canvas_width) / u64::from(viewport.width)) as usize);
+        let canvas_y = |logical_y: u32| logical_y.checked_sub(viewport.y).filter(|&viewport_y| viewport_y < viewport.height).map(|viewport_y| (u64::from(viewport_y) * u64::from(canvas_height) / u64::from(viewport.height)) as usize * canvas_width as usize);
+        // with a 90° or 270° rotation, screen columns determine the canvas row and vice versa
+        let swaps_axes = output.rotation.swaps_axes();
+        self.columns = (0..screen_width).map(|x| {
+            let (logical_x, logical_y) = output.to_logical(x, 0, screen_width, screen_height);
+            if swaps_axes { canvas_y(logical_y) } else { canvas_x(logical_x) }
+        }).collect();
+        self.rows = (0..screen_height).map(|y| {
+            let (logical_x, logical_y) = output.to_logical(0, y, screen_width, screen_height);
+            if swaps_axes { canvas_x(logical_x) } else { canvas_y(logical_y) }
+        }).collect();
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Output {
+    pub(crate) safe_area: SafeArea,
+    pub(crate) rotation: Rotation,
+    pub(crate) mirror: Mirror,
+    pub(crate) burn_in: BurnIn,
+    /// The canvas is rendered at this fraction of the viewport's resolution and scaled up when composing, trading sharpness for frame rate.
+    pub(crate) render_scale: f32,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Self {
+            safe_area: SafeArea::default(),
+            rotation: Rotation::default(),
+            mirror: Mirror::default(),
+            burn_in: BurnIn::default(),
+            render_scale: 1.0,
+        }
+    }
+}
+
+impl Output {
+    /// The screen size as seen by the canvas, i.e. with width and height swapped if the screen is rotated by 90° or 270°.
+    pub(crate) fn logical_size(&self, screen_width: u32, screen_height: u32) -> (u32, u32) {
+        if self.rotation.swaps_axes() { (screen_height, screen_width) } else { (screen_width, screen_height) }
+    }
+
+    /// The part of the logical screen the canvas is drawn to, before pixel shifting. Safe area insets refer to the edges as seen by the audience.
+    pub(crate) fn viewport(&self, screen_width: u32, screen_height: u32) -> Viewport {
+        let (screen_width, screen_height) = self.logical_size(screen_width, screen_height);
+        let shift = self.burn_in.pixel_shift;
+        let top = self.safe_area.top.resolve(screen_height).saturating_add(shift);
+        let right = self.safe_area.right.resolve(screen_width).saturating_add(shift);
+        let bottom = self.safe_area.bottom.resolve(screen_height).saturating_add(shift);
+        let left = self.safe_area.left.resolve(screen_width).saturating_add(shift);
+        // always leave at least one pixel for the canvas
+        let x = left.min(screen_width.saturating_sub(1));
+        let y = top.min(screen_height.saturating_sub(1));
+        Viewport {
+            width: screen_width.saturating_sub(x).saturating_sub(right).max(1),
+            height: screen_height.saturating_sub(y).saturating_sub(bottom).max(1),
+            x, y,
+        }
+    }
+
+    /// The size of the canvas for the given screen size, i.e. the viewport size multiplied by the render scale.
+    pub(crate) fn canvas_size(&self, screen_width: u32, screen_height: u32) -> (u32, u32) {
+        let viewport = self.viewport(screen_width, screen_height);
+        let scale = self.render_scale();
+        (
+            ((viewport.width as f32 * scale).round() as u32).max(1),
+            ((viewport.height as f32 * scale).round() as u32).max(1),
+        )
+    }
+
+    pub(crate) fn render_scale(&self) -> f32 {
+        if self.render_scale.is_finite() { self.render_scale.clamp(0.1, 1.0) } else { 1.0 }
+    }
+
+    /// Maps a pixel on the physical screen to the logical screen, undoing mirroring and rotation.
+    fn to_logical(&self, x: u32, y: u32, screen_width: u32, screen_height: u32) -> (u32, u32) {
+        let x = if self.mirror.horizontal() { screen_width - 1 - x } else { x };
+        let y = if self.mirror.vertical() { screen_height - 1 - y } else { y };
+        match self.rotation {
+            Rotation::None => (x, y),
+            Rotation::Quarter => (y, screen_width - 1 - x),
+            Rotation::Half => (screen_width - 1 - x, screen_height - 1 - y),
+            Rotation::ThreeQuarters => (screen_height - 1 - y, x),
+        }
+    }
+
+    /// Copies the canvas into a screen buffer of `0RGB` pixels, filling the area outside the safe area with the background color.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn compose(&self, canvas: PixmapRef<'_>, background: u32, effects: Effects, pixel_map: &mut PixelMap, buffer: &mut [u32], screen_width: u32, screen_height: u32) {
+        // e.g. a minimized window, which has no rows to fill
+        if screen_width == 0 || screen_height == 0 { return }
+        let mut viewport = self.viewport(screen_width, screen_height);
+        viewport.x = viewport.x.saturating_add_signed(effects.offset.0);
+        viewport.y = viewport.y.saturating_add_signed(effects.offset.1);
+        // a NaN would be cast to 0 and turn the screen black
+        let brightness = if effects.brightness.is_finite() { (effects.brightness.clamp(0.0, 1.0) * 256.0) as u32 } else { 256 };
+        let apply_effects = |mut color: u32| {
+            if effects.invert {
+                color ^= 0xffffff;
+            }
+            if brightness < 256 {
+                let dim = |channel: u32| ((channel & 0xff) * brightness) >> 8;
+                color = (dim(color >> 16) << 16) | (dim(color >> 8) << 8) | dim(color);
+            }
+            color
+        };
+        let to_rgb = |src: &PremultipliedColorU8| apply_effects((u32::from(src.red()) << 16) | (u32::from(src.green()) << 8) | u32::from(src.blue()));
+        let background = apply_effects(background);
+        let pixels = canvas.pixels();
+        if self.rotation == Rotation::None && self.mirror == Mirror::None && (canvas.width(), canvas.height()) == (viewport.width, viewport.height) {
+            // fast path: each row of the viewport is a row of the canvas
+            let left = (viewport.x.min(screen_width) as usize, viewport.x.saturating_add(viewport.width).min(screen_width) as usize);
+            for (y, row) in buffer.chunks_exact_mut(screen_width as usize).enumerate() {
+                match (y as u32).checked_sub(viewport.y).filter(|&viewport_y| viewport_y < viewport.height) {
+                    Some(viewport_y) => {
+                        let (before, rest) = row.split_at_mut(left.0);
+                        let (inside, after) = rest.split_at_mut(left.1 - left.0);
+                        before.fill(background);
+                        after.fill(background);
+                        let src = &pixels[viewport_y as usize * canvas.width() as usize..][..inside.len()];
+                        for (target, src) in inside.iter_mut().zip(src) {
+                            *target = to_rgb(src);
+                        }
+                    }
+                    None => row.fill(background),
+                }
+            }
+        } else {
+            pixel_map.update(self, viewport, canvas.width(), canvas.height(), screen_width, screen_height);
+            for (row, &row_offset) in buffer.chunks_exact_mut(screen_width as usize).zip(&pixel_map.rows) {
+                let Some(row_offset) = row_offset else {
+                    row.fill(background);
+                    continue
+                };
+                for (target, &column_offset) in row.iter_mut().zip(&pixel_map.columns) {
+                    *target = match column_offset {
+                        Some(column_offset) => to_rgb(&pixels[row_offset + column_offset]),
+                        None => background,
+                    };
+                }
+            }
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn present<D: raw_window_handle::HasDisplayHandle, W: raw_window_handle::HasWindowHandle>(&self, canvas: PixmapRef<'_>, background: u32, effects: Effects, pixel_map: &mut PixelMap, mut buffer: softbuffer::Buffer<'_, D, W>, screen_width: u32, screen_height: u32) -> Result<(), SoftBufferError> {
+        self.compose(canvas, background, effects, pixel_map, &mut buffer, screen_width, screen_height);
+        buffer.present() //TODO calculate changed rects and use present_with_damage? (need to keep track of previous coords and sizes of all items)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn safe_area(s: &str) -> SafeArea {
+        s.parse().expect("failed to parse safe area")
+    }
+
+    #[test]
+    fn inset() {
+        assert_eq!("20".parse::<Inset>().expect("failed to parse inset"), Inset::Pixels(20));
+        assert_eq!("20px".parse::<Inset>().expect("failed to parse inset"), Inset::Pixels(20));
+        assert_eq!("2.5%".parse::<Inset>().expect("failed to parse inset"), Inset::Percent(2.5));
+        assert_eq!("0%".parse::<Inset>().expect("failed to parse inset"), Inset::Percent(0.0));
+        for invalid in ["50%", "-1%", "NaN%", "%", "-1", "1.5", "20 px", "abc", ""] {
+            assert!(matches!(invalid.parse::<Inset>(), Err(SafeAreaParseError::Inset(_))), "{invalid:?} was accepted");
+        }
+    }
+
+    #[test]
+    fn margin_shorthand() {
+        let px = Inset::Pixels;
+        assert_eq!(safe_area("20"), SafeArea { top: px(20), right: px(20), bottom: px(20), left: px(20) });
+        assert_eq!(safe_area("20 40"), SafeArea { top: px(20), right: px(40), bottom: px(20), left: px(40) });
+        assert_eq!(safe_area("1 2 3"), SafeArea { top: px(1), right: px(2), bottom: px(3), left: px(2) });
+        assert_eq!(safe_area("1 2 3 4"), SafeArea { top: px(1), right: px(2), bottom: px(3), left: px(4) });
+        assert_eq!(safe_area("1,2, 3  4px"), SafeArea { top: px(1), right: px(2), bottom: px(3), left: px(4) });
+        assert_eq!(safe_area("2% 10"), SafeArea { top: Inset::Percent(2.0), right: px(10), bottom: Inset::Percent(2.0), left: px(10) });
+        assert_eq!(safe_area(&safe_area("1 2% 3 4").to_string()), safe_area("1 2% 3 4"));
+    }
+
+    #[test]
+    fn invalid_safe_area() {
+        assert!(matches!("".parse::<SafeArea>(), Err(SafeAreaParseError::Count(0))));
+        assert!(matches!("1 2 3 4 5".parse::<SafeArea>(), Err(SafeAreaParseError::Count(5))));
+        assert!(matches!("1 2 x".parse::<SafeArea>(), Err(SafeAreaParseError::Inset(inset)) if inset == "x"));
+    }
+
+    #[test]
+    fn viewport() {
+        let output = |safe_area_str: &str, rotation| Output { safe_area: safe_area(safe_area_str), rotation, ..Output::default() };
+        assert_eq!(output("0", Rotation::None).viewport(1920, 1080), Viewport { x: 0, y: 0, width: 1920, height: 1080 });
+        assert_eq!(output("10%", Rotation::None).viewport(1920, 1080), Viewport { x: 192, y: 108, width: 1536, height: 864 });
+        assert_eq!(output("1 2 3 4", Rotation::None).viewport(1920, 1080), Viewport { x: 4, y: 1, width: 1914, height: 1076 });
+        // insets refer to the rotated edges, and percentages to the rotated size
+        assert_eq!(output("0 0 0 10%", Rotation::Quarter).viewport(1920, 1080), Viewport { x: 108, y: 0, width: 972, height: 1920 });
+        // at least one pixel is left for the canvas
+        assert_eq!(output("5000", Rotation::None).viewport(1920, 1080), Viewport { x: 1919, y: 1079, width: 1, height: 1 });
+        assert_eq!(output("49%", Rotation::None).viewport(100, 100), Viewport { x: 49, y: 49, width: 2, height: 2 });
+    }
+
+    const ROTATIONS: [Rotation; 4] = [Rotation::None, Rotation::Quarter, Rotation::Half, Rotation::ThreeQuarters];
+    const MIRRORS: [Mirror; 4] = [Mirror::None, Mirror::Horizontal, Mirror::Vertical, Mirror::Both];
+
+    #[test]
+    fn rotation_and_mirror_corners() {
+        // where the top left, top right, and bottom left pixels of a 4×3 screen are taken from on the logical screen
+        for (rotation, mirror, expected) in [
+            (Rotation::None, Mirror::None, [(0, 0), (3, 0), (0, 2)]),
+            (Rotation::Quarter, Mirror::None, [(0, 3), (0, 0), (2, 3)]),
+            (Rotation::Half, Mirror::None, [(3, 2), (0, 2), (3, 0)]),
+            (Rotation::ThreeQuarters, Mirror::None, [(2, 0), (2, 3), (0, 0)]),
+            (Rotation::None, Mirror::Horizontal, [(3, 0), (0, 0), (3, 2)]),
+            (Rotation::None, Mirror::Vertical, [(0, 2), (3, 2), (0, 0)]),
+            (Rotation::None, Mirror::Both, [(3, 2), (0, 2), (3, 0)]),
+            (Rotation::Quarter, Mirror::Horizontal, [(0, 0), (0, 3), (2, 0)]),
+            (Rotation::Quarter, Mirror::Vertical, [(2, 3), (2, 0), (0, 3)]),
+            (Rotation::Quarter, Mirror::Both, [(2, 0), (2, 3), (0, 0)]),
+            (Rotation::Half, Mirror::Horizontal, [(0, 2), (3, 2), (0, 0)]),
+            (Rotation::Half, Mirror::Vertical, [(3, 0), (0, 0), (3, 2)]),
+            (Rotation::Half, Mirror::Both, [(0, 0), (3, 0), (0, 2)]),
+            (Rotation::ThreeQuarters, Mirror::Horizontal, [(2, 3), (2, 0), (0, 3)]),
+            (Rotation::ThreeQuarters, Mirror::Vertical, [(0, 0), (0, 3), (2, 0)]),
+            (Rotation::ThreeQuarters, Mirror::Both, [(0, 3), (0, 0), (2, 3)]),
+        ] {
+            let output = Output { rotation, mirror, ..Output::default() };
+            assert_eq!([(0, 0), (3, 0), (0, 2)].map(|(x, y)| output.to_logical(x, y, 4, 3)), expected, "rotation {rotation}, mirror {mirror:?}");
+        }
+    }
+
+    #[test]
+    fn rotation_and_mirror_cover_the_logical_screen() {
+        for rotation in ROTATIONS {
+            for mirror in MIRRORS {
+                let output = Output { rotation, mirror, ..Output::default() };
+                let (logical_width, logical_height) = output.logical_size(4, 3);
+                let mut seen = [false; 12];
+                for y in 0..3 {
+                    for x in 0..4 {
+                        let (logical_x, logical_y) = output.to_logical(x, y, 4, 3);
+                        assert!(logical_x < logical_width && logical_y < logical_height, "rotation {rotation}, mirror {mirror:?}: ({x}, {y}) maps to ({logical_x}, {logical_y})");
+                        assert!(!std::mem::replace(&mut seen[(logical_y * logical_width + logical_x) as usize], true), "rotation {rotation}, mirror {mirror:?}: ({logical_x}, {logical_y}) is shown twice");
+                    }
+                }
+            }
+        }
+    }
+
+    fn utc(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).expect("invalid test time").with_timezone(&Utc)
+    }
+
+    #[test]
+    fn pixel_shift_path() {
+        assert_eq!(pixel_shift_offset(0, 0), (0, 0));
+        assert_eq!(pixel_shift_offset(0, 12345), (0, 0));
+        for max in 1..=3 {
+            let side = max as usize * 2 + 1;
+            let period = 2 * side * side - 2;
+            let mut seen = vec![false; side * side];
+            let mut prev = pixel_shift_offset(max, 0);
+            assert_eq!(prev, (-(max as i32), -(max as i32)));
+            for step in 1..=period as u64 {
+                let (x, y) = pixel_shift_offset(max, step);
+                assert!(x.unsigned_abs() <= max && y.unsigned_abs() <= max, "offset ({x}, {y}) exceeds {max}");
+                assert_eq!((x - prev.0).abs() + (y - prev.1).abs(), 1, "step {step} from {prev:?} to ({x}, {y}) isn't a single pixel");
+                seen[(y + max as i32) as usize * side + (x + max as i32) as usize] = true;
+                prev = (x, y);
+            }
+            assert!(seen.into_iter().all(|seen| seen), "not every offset up to {max} is used");
+            assert_eq!(prev, pixel_shift_offset(max, 0), "path doesn't return to the start");
+        }
+    }
+
+    #[test]
+    fn no_effects() {
+        assert_eq!(BurnIn::default().effects(utc("2024-12-31T23:59:59Z")), (Effects::default(), None));
+    }
+
+    #[test]
+    fn pixel_shift_effect() {
+        let burn_in = BurnIn { pixel_shift: 1, pixel_shift_interval_secs: 60, ..BurnIn::default() };
+        assert_eq!(burn_in.effects(utc("1970-01-01T00:01:30Z")), (Effects { offset: (0, -1), ..Effects::default() }, Some(Duration::from_secs(30))));
+    }
+
+    #[test]
+    fn invert_effect() {
+        let burn_in = BurnIn { invert: Some(Invert { every_mins: 10, for_secs: 30 }), ..BurnIn::default() };
+        assert_eq!(burn_in.effects(utc("2024-12-31T23:50:10Z")), (Effects { invert: true, ..Effects::default() }, Some(Duration::from_secs(20))));
+        assert_eq!(burn_in.effects(utc("2024-12-31T23:55:00Z")), (Effects::default(), Some(Duration::from_secs(5 * 60))));
+    }
+
+    #[test]
+    fn dim_effect() {
+        let burn_in = BurnIn { dim: Some(Dim { from: NaiveTime::from_hms_opt(23, 0, 0).expect("invalid test time"), to: NaiveTime::from_hms_opt(6, 0, 0).expect("invalid test time"), brightness: 0.25 }), ..BurnIn::default() };
+        let dimmed = Effects { brightness: 0.25, ..Effects::default() };
+        assert_eq!(burn_in.effects(utc("2025-01-01T02:00:00Z")), (dimmed, Some(Duration::from_secs(4 * 60 * 60))));
+        assert_eq!(burn_in.effects(utc("2024-12-31T23:00:00Z")), (dimmed, Some(Duration::from_secs(7 * 60 * 60))));
+        assert_eq!(burn_in.effects(utc("2024-12-31T12:00:00Z")), (Effects::default(), Some(Duration::from_secs(11 * 60 * 60))));
+        // the time of day is taken from the timezone of the given time
+        assert_eq!(burn_in.effects(utc("2024-12-31T12:00:00Z").with_timezone(&chrono_tz::Pacific::Auckland)), (dimmed, Some(Duration::from_secs(5 * 60 * 60))));
+    }
+
+    #[test]
+    fn non_finite_brightness() {
+        let burn_in = BurnIn { dim: Some(Dim { from: NaiveTime::from_hms_opt(23, 0, 0).expect("invalid test time"), to: NaiveTime::from_hms_opt(6, 0, 0).expect("invalid test time"), brightness: f32::NAN }), ..BurnIn::default() };
+        assert_eq!(burn_in.effects(utc("2025-01-01T02:00:00Z")).0, Effects::default());
+        let mut canvas = tiny_skia::Pixmap::new(1, 1).expect("failed to create canvas");
+        canvas.fill(tiny_skia::Color::WHITE);
+        for brightness in [f32::NAN, f32::INFINITY] {
+            let mut buffer = [0];
+            Output::default().compose(canvas.as_ref(), 0, Effects { brightness, ..Effects::default() }, &mut PixelMap::default(), &mut buffer, 1, 1);
+            assert_eq!(buffer, [0xffffff]);
+        }
+    }
+
+    #[test]
+    fn pixel_shift_is_symmetric() {
+        const WHITE: u32 = 0xffffff;
+
+        let output = Output { burn_in: BurnIn { pixel_shift: 2, ..BurnIn::default() }, ..Output::default() };
+        assert_eq!(output.viewport(10, 10), Viewport { x: 2, y: 2, width: 6, height: 6 });
+        let mut canvas = tiny_skia::Pixmap::new(6, 6).expect("failed to create canvas");
+        canvas.fill(tiny_skia::Color::WHITE);
+        let mut buffer = [0; 100];
+        for (offset, first, last) in [((-2, -2), 0, 5), ((0, 0), 2, 7), ((2, 2), 4, 9), ((-2, 2), 0, 5)] {
+            output.compose(canvas.as_ref(), 0, Effects { offset, ..Effects::default() }, &mut PixelMap::default(), &mut buffer, 10, 10);
+            let row = (offset.1 + 2 + 2) as usize * 10;
+            let white = (0..10).filter(|&x| buffer[row + x] == WHITE).collect::<Vec<_>>();
+            assert_eq!(white, (first..=last).collect::<Vec<_>>(), "offset {offset:?}");
+        }
+    }
+
+    /// Composes pixel by pixel, as a reference for the lookup tables and the fast path.
+    fn compose_reference(output: &Output, canvas: PixmapRef<'_>, background: u32, offset: (i32, i32), screen_width: u32, screen_height: u32) -> Vec<u32> {
+        let mut viewport = output.viewport(screen_width, screen_height);
+        viewport.x = viewport.x.saturating_add_signed(offset.0);
+        viewport.y = viewport.y.saturating_add_signed(offset.1);
+        let mut buffer = Vec::default();
+        for y in 0..screen_height {
+            for x in 0..screen_width {
+                let (logical_x, logical_y) = output.to_logical(x, y, screen_width, screen_height);
+                buffer.push(if_chain::if_chain! {
+                    if let Some(viewport_x) = logical_x.checked_sub(viewport.x).filter(|&viewport_x| viewport_x < viewport.width);
+                    if let Some(viewport_y) = logical_y.checked_sub(viewport.y).filter(|&viewport_y| viewport_y < viewport.height);
+                    if let Some(src) = canvas.pixel(viewport_x * canvas.width() / viewport.width, viewport_y * canvas.height() / viewport.height);
+                    then {
+                        (u32::from(src.red()) << 16) | (u32::from(src.green()) << 8) | u32::from(src.blue())
+                    } else {
+                        background
+                    }
+                });
+            }
+        }
+        buffer
+    }
+
+    #[test]
+    fn compose_matches_reference() {
+        const SCREEN: (u32, u32) = (13, 7);
+
+        let mut pixel_map = PixelMap::default();
+        for rotation in ROTATIONS {
+            for mirror in MIRRORS {
+                for render_scale in [1.0, 0.5] {
+                    for offset in [(0, 0), (-1, 1)] {
+                        let output = Output { rotation, mirror, render_scale, safe_area: safe_area("1 2 0 1"), burn_in: BurnIn { pixel_shift: 1, ..BurnIn::default() } };
+                        let (canvas_width, canvas_height) = output.canvas_size(SCREEN.0, SCREEN.1);
+                        let mut canvas = tiny_skia::Pixmap::new(canvas_width, canvas_height).expect("failed to create canvas");
+                        for (idx, pixel) in canvas.pixels_mut().iter_mut().enumerate() {
+                            *pixel = tiny_skia::ColorU8::from_rgba(idx as u8, (idx / 256) as u8, 0x80, 0xff).premultiply();
+                        }
+                        let mut buffer = [0; (SCREEN.0 * SCREEN.1) as usize];
+                        output.compose(canvas.as_ref(), 0x123456, Effects { offset, ..Effects::default() }, &mut pixel_map, &mut buffer, SCREEN.0, SCREEN.1);
+                        assert_eq!(buffer[..], compose_reference(&output, canvas.as_ref(), 0x123456, offset, SCREEN.0, SCREEN.1), "rotation {rotation}, mirror {mirror:?}, render scale {render_scale}, offset {offset:?}");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compose_zero_size() {
+        let mut pixel_map = PixelMap::default();
+        let canvas = tiny_skia::Pixmap::new(1, 1).expect("failed to create canvas");
+        for (screen_width, screen_height) in [(0, 0), (0, 7), (13, 0)] {
+            Output::default().compose(canvas.as_ref(), 0, Effects::default(), &mut pixel_map, &mut [], screen_width, screen_height);
+        }
+    }
+}
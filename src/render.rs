@@ -0,0 +1,287 @@
+//! Drawing states into image files without opening a window, see `sil render`.
+
+use {
+    std::{
+        borrow::Cow,
+        path::PathBuf,
+        sync::Arc,
+    },
+    chrono::prelude::*,
+    chrono_tz::Tz,
+    tiny_skia::{
+        ColorU8,
+        Pixmap,
+    },
+    crate::{
+        DrawCache,
+        Error,
+        clock::Clock,
+        config::{
+            Config,
+            Theme,
+        },
+        output::{
+            Effects,
+            Mirror,
+            Rotation,
+            SafeArea,
+        },
+        state::{
+            self,
+            State,
+        },
+    },
+};
+
+#[derive(clap::Subcommand)]
+pub(crate) enum StateSpec {
+    BinaryTime {
+        #[clap(long, default_value = "Europe/Berlin")]
+        tz: Tz,
+    },
+    Calibration,
+    CloseWindows {
+        #[clap(long, default_value = "Europe/Berlin")]
+        tz: Tz,
+    },
+    Error {
+        #[clap(default_value = "example error")]
+        message: String,
+    },
+    HexagesimalTime {
+        #[clap(long, default_value = "Europe/Berlin")]
+        tz: Tz,
+    },
+    Logo {
+        #[clap(default_value = "")]
+        msg: String,
+    },
+    NewYear {
+        #[clap(long, default_value = "Europe/Berlin")]
+        tz: Tz,
+    },
+}
+
+impl StateSpec {
+    /// Parses a state given as separate command-line arguments, e.g. `new-year --tz Pacific/Auckland`, as used by `--mock-state`.
+    ///
+    /// No arguments means binary time in UTC, which is what a bare `--mock-state` showed before it took arguments.
+    pub(crate) fn parse_args(args: Vec<String>) -> Result<Self, clap::Error> {
+        #[derive(clap::Parser)]
+        #[clap(name = "sil --mock-state", no_binary_name = true)]
+        struct MockState {
+            #[clap(subcommand)]
+            state: StateSpec,
+        }
+
+        if args.is_empty() {
+            return Ok(Self::BinaryTime { tz: chrono_tz::Etc::UTC })
+        }
+        <MockState as clap::Parser>::try_parse_from(args).map(|MockState { state }| state)
+    }
+
+    /// The timezone in which the state displays the time, if any.
+    pub(crate) fn timezone(&self) -> Option<Tz> {
+        match *self {
+            Self::BinaryTime { tz } | Self::CloseWindows { tz } | Self::HexagesimalTime { tz } | Self::NewYear { tz } => Some(tz),
+            Self::Calibration | Self::Error { .. } | Self::Logo { .. } => None,
+        }
+    }
+}
+
+impl From<StateSpec> for State {
+    fn from(spec: StateSpec) -> Self {
+        match spec {
+            StateSpec::BinaryTime { tz } => Self::BinaryTime(tz),
+            StateSpec::Calibration => Self::Calibration,
+            StateSpec::CloseWindows { tz } => Self::CloseWindows(tz),
+            StateSpec::Error { message } => Self::Error(Arc::new(Error::Mock(message))),
+            StateSpec::HexagesimalTime { tz } => Self::HexagesimalTime(tz),
+            StateSpec::Logo { msg } => Self::Logo { msg: Cow::Owned(msg) },
+            StateSpec::NewYear { tz } => Self::NewYear(tz),
+        }
+    }
+}
+
+#[derive(clap::Args)]
+pub(crate) struct Args {
+    #[clap(long, default_value_t = 1920)]
+    width: u32,
+    #[clap(long, default_value_t = 1080)]
+    height: u32,
+    /// Use the light theme. Overrides the config file.
+    #[clap(short, long)]
+    light: bool,
+    /// Use the dark theme. Overrides the config file.
+    #[clap(long, conflicts_with("light"))]
+    dark: bool,
+    /// Draw the state as it looks at this time, e.g. 2024-12-31T23:59:30+01:00. Defaults to the current time.
+    #[clap(long)]
+    time: Option<DateTime<FixedOffset>>,
+    /// Keep content away from the screen edges, e.g. "2%" or "20 40". Overrides the config file.
+    #[clap(long)]
+    safe_area: Option<SafeArea>,
+    /// Rotate the display clockwise by 0, 90, 180, or 270 degrees. Overrides the config file.
+    #[clap(long)]
+    rotate: Option<Rotation>,
+    /// Mirror the display. Overrides the config file.
+    #[clap(long, value_enum)]
+    mirror: Option<Mirror>,
+    /// Render at a fraction of the native resolution (between 0.1 and 1). Overrides the config file.
+    #[clap(long)]
+    render_scale: Option<f32>,
+    #[clap(short, long, default_value = "sil.png")]
+    output: PathBuf,
+    #[clap(subcommand)]
+    state: StateSpec,
+}
+
+pub(crate) async fn render(http_client: &reqwest::Client, config: &Config, Args { width, height, light, dark, time, safe_area, rotate, mirror, render_scale, output, state }: Args) -> Result<(), Error> {
+    let mut cache = DrawCache::new(config, config.theme.with_flags(light, dark) == Theme::Dark, crate::output(&config.display, safe_area, rotate, mirror, render_scale), state.into()).await?;
+    match state::load_images(http_client, config).await {
+        Ok(images) => cache.renderer.icons.extend(images),
+        Err(e) => eprintln!("failed to load images, rendering without them: {e}"),
+    }
+    let (canvas_width, canvas_height) = cache.output.canvas_size(width, height);
+    cache.canvas = Pixmap::new(canvas_width, canvas_height).ok_or(Error::Pixmap)?;
+    cache.screen_size = (width, height);
+    cache.clock = Clock::Fixed(time.map_or_else(Utc::now, |time| time.with_timezone(&Utc)));
+    cache.draw();
+    // compose the canvas onto the screen like the display does, so the safe area, rotation, and render scale match the device
+    let mut buffer = vec![0; width as usize * height as usize];
+    let background = cache.background();
+    // burn-in effects depend on the device's local time, so they're left out
+    cache.output.compose(cache.canvas.as_ref(), background, Effects::default(), &mut cache.pixel_map, &mut buffer, width, height);
+    let mut screen = Pixmap::new(width, height).ok_or(Error::Pixmap)?;
+    for (pixel, color) in screen.pixels_mut().iter_mut().zip(buffer) {
+        *pixel = ColorU8::from_rgba((color >> 16) as u8, (color >> 8) as u8, color as u8, 0xff).premultiply();
+    }
+    screen.save_png(output)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    //! Snapshot tests comparing rendered states against the reference images in `tests/snapshots`.
+    //!
+    //! Run with the environment variable `SIL_UPDATE_SNAPSHOTS=1` to (re)generate the reference images after an intentional change, and review the diff before committing.
+
+    use {
+        std::{
+            env,
+            path::Path,
+        },
+        crate::{
+            font::Fonts,
+            glyph_cache::GlyphCache,
+            markup,
+            output::Output,
+        },
+        super::*,
+    };
+
+    /// Maximum difference per color channel that is still considered equal, to allow for small differences in antialiasing between platforms.
+    const CHANNEL_TOLERANCE: u8 = 16;
+    /// Maximum fraction of pixels that may differ by more than `CHANNEL_TOLERANCE`.
+    const PIXEL_TOLERANCE: f64 = 0.001;
+
+    const RESOLUTIONS: [(u32, u32); 2] = [(1920, 1080), (1024, 768)];
+
+    fn time(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339).expect("invalid test time").with_timezone(&Utc)
+    }
+
+    fn render(state: State, dark: bool, width: u32, height: u32, now: DateTime<Utc>) -> Pixmap {
+        let renderer = markup::Renderer::new(Fonts::bundled(), GlyphCache::new(32 * 1024 * 1024));
+        let mut cache = DrawCache::with_renderer(renderer, dark, Output::default(), state).expect("failed to create draw cache");
+        cache.canvas = Pixmap::new(width, height).expect("failed to create canvas");
+        cache.screen_size = (width, height);
+        cache.clock = Clock::Fixed(now);
+        cache.draw();
+        cache.canvas
+    }
+
+    fn assert_snapshot(name: &str, actual: &Pixmap) {
+        let snapshots = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots");
+        let reference_path = snapshots.join(format!("{name}.png"));
+        if env::var_os("SIL_UPDATE_SNAPSHOTS").is_some() {
+            std::fs::create_dir_all(&snapshots).expect("failed to create snapshots directory");
+            actual.save_png(&reference_path).expect("failed to save snapshot");
+            return
+        }
+        let reference = Pixmap::load_png(&reference_path).unwrap_or_else(|e| panic!("failed to load {}: {e} (run with SIL_UPDATE_SNAPSHOTS=1 to create it)", reference_path.display()));
+        let mismatch = if (reference.width(), reference.height()) != (actual.width(), actual.height()) {
+            Some(format!("size is {}x{}, expected {}x{}", actual.width(), actual.height(), reference.width(), reference.height()))
+        } else {
+            let differing = reference.pixels().iter().zip(actual.pixels()).filter(|(expected, actual)| {
+                expected.red().abs_diff(actual.red()) > CHANNEL_TOLERANCE
+                || expected.green().abs_diff(actual.green()) > CHANNEL_TOLERANCE
+                || expected.blue().abs_diff(actual.blue()) > CHANNEL_TOLERANCE
+            }).count();
+            (differing as f64 > reference.pixels().len() as f64 * PIXEL_TOLERANCE).then(|| format!("{differing} pixels differ"))
+        };
+        if let Some(mismatch) = mismatch {
+            let actual_path = Path::new(env!("CARGO_MANIFEST_DIR")).join("target").join("snapshots").join(format!("{name}.png"));
+            std::fs::create_dir_all(actual_path.parent().expect("snapshot path has no parent")).expect("failed to create output directory");
+            actual.save_png(&actual_path).expect("failed to save actual output");
+            panic!("snapshot {name} does not match: {mismatch} (actual output saved to {})", actual_path.display());
+        }
+    }
+
+    fn check(name: &str, state: impl Fn() -> State, now: &str) {
+        for (width, height) in RESOLUTIONS {
+            assert_snapshot(&format!("{name}-{width}x{height}"), &render(state(), true, width, height, time(now)));
+        }
+    }
+
+    #[test]
+    fn binary_time() {
+        check("binary-time", || State::BinaryTime(chrono_tz::Europe::Berlin), "2024-12-31T21:34:56+01:00");
+    }
+
+    #[test]
+    fn calibration() {
+        check("calibration", || State::Calibration, "2024-12-31T12:00:00+01:00");
+    }
+
+    #[test]
+    fn close_windows() {
+        check("close-windows", || State::CloseWindows(chrono_tz::Europe::Berlin), "2024-12-31T22:01:02+01:00");
+    }
+
+    #[test]
+    fn error() {
+        check("error", || State::Error(Arc::new(Error::Mock("example error".to_owned()))), "2024-12-31T12:00:00+01:00");
+    }
+
+    #[test]
+    fn hexagesimal_time() {
+        check("hexagesimal-time", || State::HexagesimalTime(chrono_tz::Europe::Berlin), "2024-12-31T12:34:56+01:00");
+    }
+
+    #[test]
+    fn logo() {
+        check("logo", || State::Logo { msg: Cow::Borrowed("getting current event") }, "2024-12-31T12:00:00+01:00");
+    }
+
+    #[test]
+    fn new_year() {
+        check("new-year-hours", || State::NewYear(chrono_tz::Europe::Berlin), "2024-12-31T21:34:56+01:00");
+        check("new-year-minutes", || State::NewYear(chrono_tz::Europe::Berlin), "2024-12-31T23:12:34+01:00");
+        check("new-year-seconds", || State::NewYear(chrono_tz::Europe::Berlin), "2024-12-31T23:59:30+01:00");
+        check("new-year-year", || State::NewYear(chrono_tz::Europe::Berlin), "2025-01-01T00:00:05+01:00");
+    }
+
+    #[test]
+    fn light_theme() {
+        assert_snapshot("light-close-windows-1920x1080", &render(State::CloseWindows(chrono_tz::Europe::Berlin), false, 1920, 1080, time("2024-12-31T22:01:02+01:00")));
+    }
+
+    #[test]
+    fn mock_state_args() {
+        assert!(matches!(StateSpec::parse_args(Vec::default()), Ok(StateSpec::BinaryTime { tz: chrono_tz::Etc::UTC })));
+        assert!(matches!(StateSpec::parse_args(vec!["new-year".to_owned()]), Ok(StateSpec::NewYear { tz: chrono_tz::Europe::Berlin })));
+        assert!(matches!(StateSpec::parse_args(vec!["new-year".to_owned(), "--tz".to_owned(), "Pacific/Auckland".to_owned()]), Ok(StateSpec::NewYear { tz: chrono_tz::Pacific::Auckland })));
+        assert!(StateSpec::parse_args(vec!["new-yaer".to_owned()]).is_err());
+    }
+}
@@ -0,0 +1,105 @@
+//! Choosing which mode to display next.
+
+use {
+    std::{
+        collections::HashSet,
+        hash::Hash,
+    },
+    rand::prelude::*,
+    crate::state::Priority,
+};
+
+/// Picks a random mode among those with the highest priority, showing each of them once before any is repeated.
+pub(crate) struct Scheduler<M, R> {
+    rng: R,
+    seen_modes: HashSet<M>,
+}
+
+impl<M: Copy + Eq + Hash, R: Rng> Scheduler<M, R> {
+    pub(crate) fn new(rng: R) -> Self {
+        Self {
+            rng,
+            seen_modes: HashSet::default(),
+        }
+    }
+
+    /// Chooses one of the currently available modes, or `None` if there are none.
+    pub(crate) fn next<T>(&mut self, mut available_modes: Vec<(M, Priority, T)>) -> Option<(M, Priority, T)> {
+        let max_priority = available_modes.iter().map(|(_, priority, _)| *priority).max()?;
+        available_modes.retain(|(_, iter_priority, _)| *iter_priority == max_priority);
+        if available_modes.iter().any(|(mode, _, _)| !self.seen_modes.contains(mode)) {
+            available_modes.retain(|(mode, _, _)| !self.seen_modes.contains(mode));
+        } else {
+            self.seen_modes.clear();
+        }
+        let idx = self.rng.gen_range(0..available_modes.len());
+        let (mode, priority, payload) = available_modes.swap_remove(idx);
+        self.seen_modes.insert(mode);
+        Some((mode, priority, payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        std::collections::HashSet,
+        rand::rngs::SmallRng,
+        super::*,
+    };
+
+    fn modes(modes: &[(u8, Priority)]) -> Vec<(u8, Priority, ())> {
+        modes.iter().map(|&(mode, priority)| (mode, priority, ())).collect()
+    }
+
+    #[test]
+    fn every_mode_once_per_cycle() {
+        let available = [(0, Priority::Normal), (1, Priority::Normal), (2, Priority::Normal), (3, Priority::Normal), (4, Priority::Normal)];
+        for seed in 0..100 {
+            let mut scheduler = Scheduler::new(SmallRng::seed_from_u64(seed));
+            for _ in 0..3 {
+                let cycle = (0..available.len()).map(|_| scheduler.next(modes(&available)).expect("no mode chosen").0).collect::<HashSet<_>>();
+                assert_eq!(cycle.len(), available.len(), "a mode was repeated within a cycle (seed {seed})");
+            }
+        }
+    }
+
+    #[test]
+    fn higher_priority_wins() {
+        let available = [(0, Priority::Normal), (1, Priority::Normal), (2, Priority::Programm), (3, Priority::Normal), (4, Priority::Programm)];
+        for seed in 0..100 {
+            let mut scheduler = Scheduler::new(SmallRng::seed_from_u64(seed));
+            for _ in 0..10 {
+                let (mode, priority, ()) = scheduler.next(modes(&available)).expect("no mode chosen");
+                assert_eq!(priority, Priority::Programm);
+                assert!(mode == 2 || mode == 4);
+            }
+        }
+    }
+
+    #[test]
+    fn new_modes_are_shown_next() {
+        let mut scheduler = Scheduler::new(SmallRng::seed_from_u64(0));
+        assert_eq!(scheduler.next(modes(&[(0, Priority::Normal)])).map(|(mode, _, ())| mode), Some(0));
+        // mode 1 becomes available mid-cycle and should be preferred over repeating mode 0
+        assert_eq!(scheduler.next(modes(&[(0, Priority::Normal), (1, Priority::Normal)])).map(|(mode, _, ())| mode), Some(1));
+    }
+
+    #[test]
+    fn none_only_without_modes() {
+        let mut scheduler = Scheduler::new(SmallRng::seed_from_u64(0));
+        assert!(scheduler.next(modes(&[])).is_none());
+        assert!(scheduler.next(modes(&[(0, Priority::Normal)])).is_some());
+        assert!(scheduler.next(modes(&[(0, Priority::Normal)])).is_some(), "the only mode should be repeated once its cycle ends");
+        assert!(scheduler.next(modes(&[])).is_none());
+    }
+
+    #[test]
+    fn deterministic() {
+        let available = [(0, Priority::Normal), (1, Priority::Normal), (2, Priority::Normal), (3, Priority::Normal)];
+        let run = |seed| {
+            let mut scheduler = Scheduler::new(SmallRng::seed_from_u64(seed));
+            (0..20).map(|_| scheduler.next(modes(&available)).expect("no mode chosen").0).collect::<Vec<_>>()
+        };
+        assert_eq!(run(42), run(42));
+    }
+}
@@ -1,13 +1,15 @@
 use {
     std::{
-        collections::HashSet,
+        borrow::Cow,
         convert::Infallible as Never,
-        pin::pin,
         sync::Arc,
-        time::Duration as StdDuration,
+        time::{
+            Duration as StdDuration,
+            Instant,
+        },
     },
     chrono::{
-        Duration,
+        TimeDelta,
         prelude::*,
     },
     chrono_tz::Tz,
@@ -29,44 +31,52 @@ use {
     },
     rand::prelude::*,
     semver::Version,
-    tiny_skia::Pixmap,
     tokio::{
-        io::AsyncReadExt as _,
         select,
+        sync::watch,
         time::{
-            MissedTickBehavior,
-            interval,
             sleep,
+            sleep_until,
         },
     },
     wheel::{
-        fs::{
-            self,
-            File,
-        },
+        fs,
         traits::ReqwestResponseExt as _,
     },
-    winit::event_loop::EventLoopProxy,
+    winit::event_loop::{
+        EventLoopClosed,
+        EventLoopProxy,
+    },
     crate::{
         Error,
         UserEvent,
-        config::Config,
+        clock::Clock,
+        config::{
+            Config,
+            ModesConfig,
+            UpdateConfig,
+        },
+        image::Image,
+        install::InstallPaths,
+        scheduler::Scheduler,
     },
 };
-#[cfg(unix)] use xdg::BaseDirectories;
+#[cfg(unix)] use {
+    xdg::BaseDirectories,
+    crate::install,
+};
 #[cfg(windows)] use directories::ProjectDirs;
 #[cfg(any(feature = "nixos", unix))] use {
     tokio::process::Command,
     wheel::traits::AsyncCommandOutputExt as _,
 };
-#[cfg(all(not(feature = "nixos"), unix))] use crate::REIWA_BIN_PATH;
 
 pub(crate) struct Event {
     pub(crate) timezone: Tz,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash, Sequence)]
-enum Mode {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Sequence)]
+pub(crate) enum Mode {
     BinaryTime,
     CloseWindows,
     HexagesimalTime,
@@ -75,11 +85,12 @@ enum Mode {
 }
 
 impl Mode {
-    fn state(&self, current_event: Option<&Event>) -> Option<(Priority, State)> {
+    fn state(&self, config: &ModesConfig, now: DateTime<Utc>, current_event: Option<&Event>) -> Option<(Priority, State)> {
         match self {
             Self::BinaryTime => {
+                if !config.binary_time.enabled { return None }
                 let timezone = current_event?.timezone;
-                let now = Utc::now().with_timezone(&timezone);
+                let now = now.with_timezone(&timezone);
                 let tomorrow = now.date_naive().succ_opt().expect("date overflow");
                 if tomorrow.month() == 1 && tomorrow.day() == 1 {
                     Some((Priority::Normal, State::BinaryTime(timezone)))
@@ -88,37 +99,71 @@ impl Mode {
                 }
             }
             Self::CloseWindows => {
+                if !config.close_windows.enabled { return None }
                 let timezone = current_event?.timezone;
-                let now = Utc::now().with_timezone(&timezone);
-                if now.hour() == 22 && now.minute() < 5 {
+                let now = now.with_timezone(&timezone);
+                let mut since_start = now.time() - config.close_windows.time;
+                if since_start < TimeDelta::zero() {
+                    // started the previous day
+                    since_start += TimeDelta::days(1);
+                }
+                if since_start < TimeDelta::minutes(config.close_windows.duration_mins.into()) {
                     Some((Priority::Programm, State::CloseWindows(timezone)))
                 } else {
                     None
                 }
             }
-            Self::HexagesimalTime => Some((Priority::Normal, State::HexagesimalTime(current_event?.timezone))),
+            Self::HexagesimalTime => {
+                if !config.hexagesimal_time.enabled { return None }
+                Some((Priority::Normal, State::HexagesimalTime(current_event?.timezone)))
+            }
             Self::Logo => None,
             Self::NewYear => {
+                if !config.new_year.enabled { return None }
                 let timezone = current_event?.timezone;
-                let now = Utc::now().with_timezone(&timezone);
-                if now.month() == 1 && now.day() == 1 && now.hour() == 0 {
-                    Some(Priority::Programm)
+                let now = now.with_timezone(&timezone);
+                let priority_duration = TimeDelta::minutes(config.new_year.priority_mins.into());
+                // near the end of the supported date range, the mode is skipped
+                let priority = if now.month() == 1 && now.day() == 1 {
+                    // the start of the new year, even if midnight was skipped by a DST transition
+                    (now - new_year(timezone, now.year())? < priority_duration).then_some(Priority::Programm)
                 } else {
-                    let tomorrow = now.date_naive().succ_opt().expect("date overflow");
-                    (tomorrow.month() == 1 && tomorrow.day() == 1).then(|| if timezone.from_local_datetime(&tomorrow.and_hms_opt(0, 0, 0).expect("tomorrow has no midnight")).single().expect("failed to determine tomorrow at midnight") - now < Duration::hours(1).into() {
-                        Priority::Programm
+                    let tomorrow = now.date_naive().succ_opt()?;
+                    if tomorrow.month() == 1 && tomorrow.day() == 1 {
+                        Some(if new_year(timezone, tomorrow.year())? - now < priority_duration {
+                            Priority::Programm
+                        } else {
+                            Priority::Normal
+                        })
                     } else {
-                        Priority::Normal
-                    })
-                }.map(|priority| (priority, State::NewYear(timezone)))
+                        None
+                    }
+                }?;
+                Some((priority, State::NewYear(timezone)))
             }
         }
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
-enum Priority {
-    Fallback,
+/// The first instant whose local date is January 1 of the given year.
+///
+/// Local midnight doesn't always exist exactly once: a DST transition or timezone change can skip it or (if clocks are set back shortly after midnight) repeat it.
+///
+/// `None` if the year is outside the range of supported dates.
+pub(crate) fn new_year(timezone: Tz, year: i32) -> Option<DateTime<Tz>> {
+    let midnight = NaiveDate::from_ymd_opt(year, 1, 1)?.and_time(NaiveTime::MIN);
+    // if midnight is skipped, the year starts with the first local time after the gap
+    (0..=24 * 60).find_map(|minutes| timezone.from_local_datetime(&midnight.checked_add_signed(TimeDelta::minutes(minutes))?).earliest())
+}
+
+/// The time remaining until the next new year, if `State::NewYear` should display a countdown rather than the current year.
+pub(crate) fn new_year_countdown(now: DateTime<Tz>) -> Option<TimeDelta> {
+    if now.month() <= 6 { return None }
+    Some(new_year(now.timezone(), now.year() + 1)? - now)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Priority {
     Normal,
     Programm,
 }
@@ -126,68 +171,131 @@ enum Priority {
 #[derive(Debug, Clone)]
 pub(crate) enum State {
     BinaryTime(Tz),
+    /// A test pattern for adjusting the safe area, see `--calibrate`.
+    Calibration,
     CloseWindows(Tz),
     Error(Arc<Error>),
     HexagesimalTime(Tz),
     Logo {
-        msg: &'static str,
+        msg: Cow<'static, str>,
     },
     NewYear(Tz),
 }
 
-async fn load_images_inner(http_client: &reqwest::Client, states_tx: EventLoopProxy<UserEvent>) -> Result<(), Error> {
-    if let Some(mut file) = {
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) enum Connection {
+    #[default]
+    Disconnected,
+    Connecting,
+    Connected,
+    /// `--mock-event`
+    Mock,
+}
+
+/// What the scheduler is currently doing, for the debug overlay.
+#[derive(Debug, Default, Clone)]
+pub(crate) struct Status {
+    pub(crate) mode: Option<(Mode, Priority)>,
+    pub(crate) next_rotation: Option<Instant>,
+    pub(crate) connection: Connection,
+    pub(crate) last_message: Option<(Instant, String)>,
+}
+
+impl Status {
+    fn send(&self, states_tx: &EventLoopProxy<UserEvent>) -> Result<(), EventLoopClosed<UserEvent>> {
+        tokio::task::block_in_place(|| states_tx.send_event(UserEvent::Status(Box::new(self.clone()))))
+    }
+
+    fn received(&mut self, msg: &ServerMessageV2) {
+        self.last_message = Some((Instant::now(), match msg {
+            ServerMessageV2::Ping => "ping".to_owned(),
+            ServerMessageV2::Error { display, .. } => format!("error: {display}"),
+            ServerMessageV2::NoEvent => "no current event".to_owned(),
+            ServerMessageV2::CurrentEvent { id: _, timezone } => format!("current event ({timezone})"),
+            ServerMessageV2::LatestSilVersion(version) => format!("latest version: {version}"),
+        }));
+    }
+}
+
+/// Loads the Gefolge logo (from the cache if possible) and the icons from the config.
+///
+/// Icons that can't be loaded are skipped, so a typo in one path doesn't take down the display.
+pub(crate) async fn load_images(http_client: &reqwest::Client, config: &Config) -> Result<Vec<(String, Image)>, Error> {
+    let logo_file_name = if config.logo_url.ends_with(".svg") { "gefolge.svg" } else { "gefolge.png" };
+    let cache_path = {
         #[cfg(unix)] {
-            pin!(
-                stream::iter(BaseDirectories::new().find_cache_file("fidera/gefolge.png"))
-                    .filter_map(|path| async move { File::open(path).await.ok() })
-            ).next().await
+            BaseDirectories::new().place_cache_file(format!("fidera/{logo_file_name}"))?
         }
         #[cfg(windows)] {
-            pin!(
-                fs::read_dir(ProjectDirs::from("org", "Gefolge", "sil").ok_or(Error::MissingHomeDir)?.cache_dir())
-                    .filter_map(|res| async move { File::open(res.ok()?.path()).await.ok() })
-            ).next().await
+            ProjectDirs::from("org", "Gefolge", "sil").ok_or(Error::MissingHomeDir)?.cache_dir().join(logo_file_name)
         }
-    } {
-        let mut buf = Vec::default();
-        file.read_to_end(&mut buf).await?;
-        tokio::task::block_in_place(|| Ok::<_, Error>(states_tx.send_event(UserEvent::Logo(Pixmap::decode_png(&buf)?))?))?;
+    };
+    // the URL the cached logo was downloaded from, so it's downloaded again when `logoUrl` is changed
+    let url_path = cache_path.with_file_name(format!("{logo_file_name}.url"));
+    let cached = if fs::exists(&url_path).await? && fs::read_to_string(&url_path).await? == config.logo_url {
+        fs::read(&cache_path).await.ok()
+    } else {
+        None
+    };
+    let logo = if let Some(buf) = cached {
+        Image::decode(&buf)?
     } else {
-        let cache_path = {
-            #[cfg(unix)] {
-                BaseDirectories::new().place_cache_file("fidera/gefolge.png")?
-            }
-            #[cfg(windows)] {
-                ProjectDirs::from("org", "Gefolge", "sil").ok_or(Error::MissingHomeDir)?.cache_dir().join("gefolge.png")
-            }
-        };
         fs::create_dir_all(cache_path.parent().expect("attempted to create file at filesystem root")).await?;
-        http_client.get("https://gefolge.org/static/gefolge.png")
+        http_client.get(&config.logo_url)
             .send().await?
             .detailed_error_for_status().await?
             .download(&cache_path).await?;
-        tokio::task::block_in_place(|| Ok::<_, Error>(states_tx.send_event(UserEvent::Logo(Pixmap::load_png(cache_path)?))?))?;
+        fs::write(&url_path, &config.logo_url).await?;
+        Image::decode(&fs::read(cache_path).await?)?
     };
+    let mut images = vec![("logo".to_owned(), logo)];
+    for (name, path) in &config.icons {
+        match fs::read(path).await {
+            Ok(data) => match Image::decode(&data) {
+                Ok(image) => images.push((name.clone(), image)),
+                Err(e) => eprintln!("skipping icon {name:?}: failed to decode {}: {e}", path.display()),
+            },
+            Err(e) => eprintln!("skipping icon {name:?}: {e}"),
+        }
+    }
+    Ok(images)
+}
+
+async fn load_images_inner(http_client: &reqwest::Client, config: &Config, states_tx: EventLoopProxy<UserEvent>) -> Result<(), Error> {
+    for (name, image) in load_images(http_client, config).await? {
+        tokio::task::block_in_place(|| states_tx.send_event(UserEvent::Icon(name, image)))?;
+    }
     Ok(())
 }
 
-async fn update_check(states_tx: EventLoopProxy<UserEvent>, allow_self_update: bool, version: Version) -> Result<(), Error> {
+#[cfg_attr(any(feature = "nixos", not(unix)), allow(unused_variables))] // the HTTP client, config, and install paths are only needed for downloading updates on unix
+async fn update_check(http_client: &reqwest::Client, config: &UpdateConfig, states_tx: EventLoopProxy<UserEvent>, allow_self_update: bool, install_paths: &InstallPaths, version: Version) -> Result<(), Error> {
     if version <= env!("CARGO_PKG_VERSION").parse().expect("failed to parse package version") {
         Ok(())
     } else {
         if allow_self_update {
             #[cfg(feature = "nixos")] {
-                tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: "updating Nix dependencies" })))?;
+                tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: Cow::Borrowed("updating Nix dependencies") })))?;
                 Command::new("nix").arg("flake").arg("update").current_dir("/etc/nixos").check("nix flake update").await?;
-                tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: "switching NixOS config" })))?;
+                tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: Cow::Borrowed("switching NixOS config") })))?;
                 Command::new("sudo").arg("nixos-rebuild").arg("switch").check("nixos-rebuild").await?;
             }
             #[cfg(not(feature = "nixos"))] {
                 #[cfg(unix)] {
+                    if install::is_skipped(install_paths, &version).await? {
+                        println!("not updating sil to {version} since it was rolled back recently");
+                        return Ok(())
+                    }
                     println!("updating sil from {} to {}", env!("CARGO_PKG_VERSION"), version);
-                    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: "downloading update" })))?;
-                    Command::new("scp").arg("reiwa:/opt/git/github.com/dasgefolge/sil/main/target/release/sil").arg(REIWA_BIN_PATH).check("scp").await?;
+                    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: Cow::Borrowed("downloading update") })))?;
+                    match (config.url(), config.public_key()) {
+                        (Some(url), Some(public_key)) => install::download(http_client, url, public_key, &version, &install_paths.staged_bin).await?,
+                        (Some(_), None) => return Err(Error::MissingUpdatePublicKey),
+                        (None, _) => {
+                            println!("no update URL configured, copying the update from reiwa via scp");
+                            Command::new("scp").arg("reiwa:/opt/git/github.com/dasgefolge/sil/main/target/release/sil").arg(&install_paths.staged_bin).check("scp").await?;
+                        }
+                    }
                 }
             }
         }
@@ -196,15 +304,28 @@ async fn update_check(states_tx: EventLoopProxy<UserEvent>, allow_self_update: b
     }
 }
 
-async fn maintain_inner(mut rng: impl Rng + Send, http_client: &reqwest::Client, mock_event: bool, allow_self_update: bool, ws_url: String, states_tx: EventLoopProxy<UserEvent>) -> Result<Never, Error> {
-    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: "loading Gefolge logo" })))?;
-    load_images_inner(http_client, states_tx.clone()).await?;
+/// The real instant of the next mode rotation. The interval is measured on the clock, so `--time-scale` speeds up the rotation along with the modes' timing.
+fn rotation_deadline(config: &Config, clock: &Clock) -> Instant {
+    let interval = StdDuration::from_secs(config.scheduler.rotation_interval_secs.max(1));
+    // keep rotating in real time if the clock is stopped
+    clock.deadline(interval).unwrap_or_else(|| Instant::now() + interval)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn maintain_inner(mut rng: impl Rng + Send, http_client: &reqwest::Client, mut config_rx: watch::Receiver<Arc<Config>>, mut clock: Clock, mock_event: bool, allow_self_update: bool, install_paths: InstallPaths, ws_url: Option<String>, states_tx: EventLoopProxy<UserEvent>) -> Result<Never, Error> {
+    let mut config = config_rx.borrow_and_update().clone();
+    let ws_url = ws_url.unwrap_or_else(|| config.ws_url.clone());
+    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: Cow::Borrowed("loading Gefolge logo") })))?;
+    load_images_inner(http_client, &config, states_tx.clone()).await?;
     if rng.gen_bool(0.1) {
-        tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: "reticulating splines" })))?;
+        tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: Cow::Borrowed("reticulating splines") })))?;
         sleep(StdDuration::from_secs_f64(rng.gen_range(0.5..1.5))).await;
     }
-    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: "getting current event" })))?;
+    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: Cow::Borrowed("getting current event") })))?;
+    let mut status = Status::default();
     let (mut stream, mut current_event) = if mock_event {
+        status.connection = Connection::Mock;
+        status.send(&states_tx)?;
         (
             Either::Left(stream::pending::<Result<ServerMessageV2, async_proto::ReadError>>()),
             Some(Event {
@@ -212,62 +333,243 @@ async fn maintain_inner(mut rng: impl Rng + Send, http_client: &reqwest::Client,
             }),
         )
     } else {
-        let config = Config::load().await?;
-        let (mut sink, mut stream) = async_proto::websocket027(ws_url).await?;
-        sink.send(ClientMessageV2::Auth {
-            api_key: config.api_key,
-        }).await?;
-        sink.send(ClientMessageV2::CurrentEvent).await?;
+        let api_key = config.api_key()?.to_owned();
+        status.connection = Connection::Connecting;
+        status.send(&states_tx)?;
+        let (mut sink, mut stream) = async_proto::websocket027(String::from(config.display.ws_url(&ws_url)?)).await.map_err(connect_error)?;
+        sink.send(ClientMessageV2::Auth { api_key }).await.map_err(connect_error)?;
+        sink.send(ClientMessageV2::CurrentEvent).await.map_err(connect_error)?;
+        status.connection = Connection::Connected;
+        status.send(&states_tx)?;
         let current_event = loop {
-            break match stream.next().await.ok_or(Error::EndOfStream)?? {
+            let msg = stream.next().await.ok_or(Error::EndOfStream).map_err(connect_error)?.map_err(connect_error)?;
+            status.received(&msg);
+            status.send(&states_tx)?;
+            break match msg {
                 ServerMessageV2::Ping => continue, //TODO send pong
-                ServerMessageV2::Error { debug, display } => return Err(Error::Server { debug, display }),
+                // e.g. an invalid API key
+                ServerMessageV2::Error { debug, display } => return Err(connect_error(Error::Server { debug, display })),
                 ServerMessageV2::NoEvent => None,
                 ServerMessageV2::CurrentEvent { id: _, timezone } => Some(Event { timezone }),
                 ServerMessageV2::LatestSilVersion(version) => {
-                    update_check(states_tx.clone(), allow_self_update, version).await?; //TODO run in background
+                    update_check(http_client, &config.update, states_tx.clone(), allow_self_update, &install_paths, version).await?; //TODO run in background
                     continue
                 }
             }
         };
         (Either::Right(stream), current_event)
     };
-    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: "determining first mode" })))?;
-    let mut seen_modes = HashSet::new();
-    let mut interval = interval(StdDuration::from_secs(10));
-    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // a `--fake-time` without an offset refers to the event's timezone, or the system timezone if there is no current event
+    if match current_event {
+        Some(Event { timezone }) => clock.resolve(&timezone),
+        None => clock.resolve(&Local),
+    } {
+        tokio::task::block_in_place(|| states_tx.send_event(UserEvent::Clock(clock.clone())))?;
+    }
+    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: Cow::Borrowed("determining first mode") })))?;
+    // connected successfully, so a freshly installed update is confirmed once it keeps running for a while
+    #[cfg(unix)] let mut healthy_at = install::is_pending(&install_paths).await?.then(|| Instant::now() + install::TRIAL_PERIOD);
+    #[cfg(not(unix))] let mut healthy_at = None::<Instant>;
+    let mut scheduler = Scheduler::new(rng);
+    let mut next_rotation = Instant::now();
     loop {
         select! {
-            res = stream.next() => match res.ok_or(Error::EndOfStream)?? {
-                ServerMessageV2::Ping => continue, //TODO send pong
-                ServerMessageV2::Error { debug, display } => return Err(Error::Server { debug, display }),
-                ServerMessageV2::NoEvent => current_event = None,
-                ServerMessageV2::CurrentEvent { id: _, timezone } => current_event = Some(Event { timezone }),
-                ServerMessageV2::LatestSilVersion(version) => update_check(states_tx.clone(), allow_self_update, version).await?, //TODO run in background
-            },
-            _ = interval.tick() => {
-                let mut available_modes = all::<Mode>().filter_map(|mode| Some((mode, mode.state(current_event.as_ref())?))).collect::<Vec<_>>();
-                let max_priority = available_modes.iter().map(|(_, (priority, _))| *priority).max().unwrap_or(Priority::Fallback);
-                available_modes.retain(|(_, (iter_priority, _))| *iter_priority == max_priority);
-                if available_modes.iter().any(|(mode, _)| !seen_modes.contains(mode)) {
-                    available_modes.retain(|(mode, _)| !seen_modes.contains(mode));
-                } else {
-                    seen_modes.clear();
+            () = sleep_until(healthy_at.unwrap_or_else(Instant::now).into()), if healthy_at.is_some() => {
+                healthy_at = None;
+                #[cfg(unix)] install::confirm(&install_paths).await?;
+            }
+            // only fails if the config watcher isn't running
+            Ok(()) = config_rx.changed() => {
+                config = config_rx.borrow_and_update().clone();
+                // switch to the new mode set and timing right away
+                next_rotation = Instant::now();
+            }
+            res = stream.next() => {
+                let msg = res.ok_or(Error::EndOfStream)??;
+                status.received(&msg);
+                status.send(&states_tx)?;
+                match msg {
+                    ServerMessageV2::Ping => continue, //TODO send pong
+                    ServerMessageV2::Error { debug, display } => return Err(Error::Server { debug, display }),
+                    ServerMessageV2::NoEvent => current_event = None,
+                    ServerMessageV2::CurrentEvent { id: _, timezone } => current_event = Some(Event { timezone }),
+                    ServerMessageV2::LatestSilVersion(version) => update_check(http_client, &config.update, states_tx.clone(), allow_self_update, &install_paths, version).await?, //TODO run in background
                 }
-                if let Some((mode, (_, new_state))) = available_modes.choose(&mut rng) {
-                    seen_modes.insert(*mode);
-                    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(new_state.clone())))?;
+            }
+            () = sleep_until(next_rotation.into()) => {
+                next_rotation = rotation_deadline(&config, &clock);
+                status.next_rotation = Some(next_rotation);
+                let now = clock.now();
+                let available_modes = all::<Mode>().filter_map(|mode| {
+                    let (priority, state) = mode.state(&config.modes, now, current_event.as_ref())?;
+                    Some((mode, priority, state))
+                }).collect();
+                if let Some((mode, priority, new_state)) = scheduler.next(available_modes) {
+                    status.mode = Some((mode, priority));
+                    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(new_state)))?;
                 } else {
-                    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: "no modes available" })))?;
+                    status.mode = None;
+                    tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Logo { msg: Cow::Borrowed("no modes available") })))?;
                 };
+                status.send(&states_tx)?;
             }
         }
     }
 }
 
-pub(crate) async fn maintain(rng: impl Rng + Send, http_client: reqwest::Client, mock_event: bool, allow_self_update: bool, ws_url: String, states_tx: EventLoopProxy<UserEvent>) {
-    match maintain_inner(rng, &http_client, mock_event, allow_self_update, ws_url, states_tx.clone()).await {
+fn connect_error(e: impl Into<Error>) -> Error {
+    Error::Connect(Box::new(e.into()))
+}
+
+/// Rolls back an update that's still in its trial period if the error shows that it doesn't work. Returns whether it did.
+#[cfg(unix)]
+async fn rollback_after(e: &Error, install_paths: &InstallPaths) -> Result<bool, wheel::Error> {
+    // other errors, such as a network outage after connecting or a missing logo, aren't caused by the update
+    if let Error::Connect(_) = e { install::rollback(install_paths).await } else { Ok(false) }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn maintain(rng: impl Rng + Send, http_client: reqwest::Client, config_rx: watch::Receiver<Arc<Config>>, clock: Clock, mock_event: bool, allow_self_update: bool, install_paths: InstallPaths, ws_url: Option<String>, states_tx: EventLoopProxy<UserEvent>) {
+    match maintain_inner(rng, &http_client, config_rx, clock, mock_event, allow_self_update, install_paths.clone(), ws_url, states_tx.clone()).await {
         Ok(never) => match never {},
-        Err(e) => { let _ = tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Error(Arc::new(e))))); }
+        Err(e) => {
+            #[cfg(unix)] match rollback_after(&e, &install_paths).await {
+                Ok(true) => {
+                    eprintln!("rolling back update: {e} ({e:?})");
+                    let _ = tokio::task::block_in_place(|| states_tx.send_event(UserEvent::UpdateDone));
+                    return
+                }
+                Ok(false) => {}
+                Err(e) => eprintln!("failed to roll back update: {e} ({e:?})"),
+            }
+            let _ = tokio::task::block_in_place(|| states_tx.send_event(UserEvent::State(State::Error(Arc::new(e)))));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        chrono_tz::TZ_VARIANTS,
+        proptest::prelude::*,
+        super::*,
+    };
+
+    fn state_at(mode: Mode, rfc3339: &str) -> Option<Priority> {
+        configured_state_at(&ModesConfig::default(), mode, rfc3339)
+    }
+
+    fn configured_state_at(config: &ModesConfig, mode: Mode, rfc3339: &str) -> Option<Priority> {
+        let now = DateTime::parse_from_rfc3339(rfc3339).expect("invalid test time").with_timezone(&Utc);
+        mode.state(config, now, Some(&Event { timezone: chrono_tz::Europe::Berlin })).map(|(priority, _)| priority)
+    }
+
+    #[test]
+    fn new_year_mode() {
+        assert_eq!(state_at(Mode::NewYear, "2024-12-30T23:30:00+01:00"), None);
+        assert_eq!(state_at(Mode::NewYear, "2024-12-31T12:00:00+01:00"), Some(Priority::Normal));
+        assert_eq!(state_at(Mode::NewYear, "2024-12-31T23:30:00+01:00"), Some(Priority::Programm));
+        assert_eq!(state_at(Mode::NewYear, "2025-01-01T00:30:00+01:00"), Some(Priority::Programm));
+        assert_eq!(state_at(Mode::NewYear, "2025-01-01T01:00:00+01:00"), None);
+    }
+
+    #[test]
+    fn close_windows() {
+        assert_eq!(state_at(Mode::CloseWindows, "2024-12-31T21:59:59+01:00"), None);
+        assert_eq!(state_at(Mode::CloseWindows, "2024-12-31T22:04:59+01:00"), Some(Priority::Programm));
+        assert_eq!(state_at(Mode::CloseWindows, "2024-12-31T22:05:00+01:00"), None);
+    }
+
+    #[test]
+    fn close_windows_configured() {
+        let mut config = ModesConfig::default();
+        config.close_windows.time = NaiveTime::from_hms_opt(23, 58, 0).expect("invalid test time");
+        assert_eq!(configured_state_at(&config, Mode::CloseWindows, "2024-12-31T23:57:59+01:00"), None);
+        assert_eq!(configured_state_at(&config, Mode::CloseWindows, "2025-01-01T00:02:59+01:00"), Some(Priority::Programm));
+        assert_eq!(configured_state_at(&config, Mode::CloseWindows, "2025-01-01T00:03:00+01:00"), None);
+        config.close_windows.enabled = false;
+        assert_eq!(configured_state_at(&config, Mode::CloseWindows, "2024-12-31T23:59:00+01:00"), None);
+    }
+
+    #[test]
+    fn end_of_time() {
+        let now = DateTime::<Utc>::MAX_UTC;
+        assert_eq!(Mode::NewYear.state(&ModesConfig::default(), now, Some(&Event { timezone: chrono_tz::Europe::Berlin })).map(|(priority, _)| priority), None);
+        assert_eq!(new_year_countdown(now.with_timezone(&chrono_tz::Europe::Berlin)), None);
+        assert_eq!(new_year(chrono_tz::Europe::Berlin, NaiveDate::MAX.year() + 1), None);
+    }
+
+    #[test]
+    fn no_event() {
+        let now = DateTime::parse_from_rfc3339("2024-12-31T23:30:00+01:00").expect("invalid test time").with_timezone(&Utc);
+        assert!(all::<Mode>().all(|mode| mode.state(&ModesConfig::default(), now, None).is_none()));
+    }
+
+    proptest! {
+        #[test]
+        fn new_year_starts_the_year(timezone in proptest::sample::select(&TZ_VARIANTS[..]), year in 1971..2100) {
+            let start = new_year(timezone, year).expect("year out of range");
+            prop_assert_eq!((start.year(), start.month(), start.day()), (year, 1, 1));
+            let before = start - TimeDelta::seconds(1);
+            prop_assert!(before.year() < year, "{} is already in {} ({})", before, year, timezone);
+        }
+
+        #[test]
+        fn new_year_eligibility(timezone in proptest::sample::select(&TZ_VARIANTS[..]), year in 1971..2100, secs_before in 1..2 * 86_400_i64) {
+            let start = new_year(timezone, year).expect("year out of range");
+            let now = start - TimeDelta::seconds(secs_before);
+            let priority = Mode::NewYear.state(&ModesConfig::default(), now.with_timezone(&Utc), Some(&Event { timezone })).map(|(priority, _)| priority);
+            if now.month() == 12 && now.day() == 31 {
+                prop_assert_eq!(priority, Some(if secs_before < 3600 { Priority::Programm } else { Priority::Normal }), "at {} ({})", now, timezone);
+            } else {
+                prop_assert_eq!(priority, None, "at {} ({})", now, timezone);
+            }
+            let now = start + TimeDelta::seconds(secs_before - 1);
+            let priority = Mode::NewYear.state(&ModesConfig::default(), now.with_timezone(&Utc), Some(&Event { timezone })).map(|(priority, _)| priority);
+            if secs_before <= 3600 {
+                prop_assert_eq!(priority, Some(Priority::Programm), "at {} ({})", now, timezone);
+            } else if !(now.month() == 1 && now.day() == 1) {
+                prop_assert_eq!(priority, None, "at {} ({})", now, timezone);
+            }
+        }
+
+        #[test]
+        fn countdown_never_negative(timezone in proptest::sample::select(&TZ_VARIANTS[..]), year in 1971..2100, secs_before in 1..184 * 86_400_i64) {
+            let now = new_year(timezone, year).expect("year out of range") - TimeDelta::seconds(secs_before);
+            if let Some(countdown) = new_year_countdown(now) {
+                prop_assert_eq!(countdown, TimeDelta::seconds(secs_before), "at {} ({})", now, timezone);
+            }
+        }
+
+        #[test]
+        fn countdown_never_skips(timezone in proptest::sample::select(&TZ_VARIANTS[..]), year in 1971..2100, secs_before in 1..7200_i64) {
+            let now = new_year(timezone, year).expect("year out of range") - TimeDelta::seconds(secs_before);
+            let countdown = new_year_countdown(now);
+            prop_assert!(countdown.is_some_and(|countdown| countdown > TimeDelta::zero()), "no countdown at {} ({})", now, timezone);
+            let next = new_year_countdown(now + TimeDelta::seconds(1));
+            if secs_before == 1 {
+                prop_assert_eq!(next, None, "countdown continues past new year in {}", timezone);
+            } else {
+                prop_assert_eq!(countdown.zip(next).map(|(countdown, next)| countdown - next), Some(TimeDelta::seconds(1)), "at {} ({})", now, timezone);
+            }
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn transient_errors_dont_roll_back() {
+        let dir = tempfile::TempDir::new().expect("failed to create temp dir");
+        let paths = InstallPaths::new(&Default::default(), &dir.path().join("sil"));
+        std::fs::write(&paths.bin, "old").expect("failed to write test file");
+        std::fs::write(&paths.staged_bin, "new").expect("failed to write test file");
+        let version = Version::new(2, 0, 1);
+        install::handoff(&paths, &paths.staged_bin, &version, None).await.expect("handoff failed");
+        install::handoff(&paths, &paths.bin, &version, None).await.expect("handoff failed");
+        for e in [Error::EndOfStream, Error::Server { debug: String::default(), display: "database unavailable".to_owned() }] {
+            assert!(!rollback_after(&e, &paths).await.expect("rollback failed"));
+        }
+        assert_eq!(std::fs::read_to_string(&paths.bin).expect("failed to read test file"), "new");
+        assert!(rollback_after(&connect_error(Error::EndOfStream), &paths).await.expect("rollback failed"));
+        assert_eq!(std::fs::read_to_string(&paths.bin).expect("failed to read test file"), "old");
     }
 }